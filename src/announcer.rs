@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::colours::roles::DARK_RED;
+use poise::serenity_prelude::prelude::TypeMapKey;
+use songbird::events::{Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent};
+use songbird::tracks::{TrackHandle, TrackState};
+
+use crate::commands::queue;
+use crate::settings;
+use crate::utils::CustomMetadata;
+
+/// The now playing message posted in a guild, and the track it's about
+struct Announcement {
+    channel_id: serenity::ChannelId,
+    message_id: serenity::MessageId,
+    trackhandle: TrackHandle,
+}
+
+impl Announcement {
+    /// Whether this message already shows `trackhandle`, like when it's resumed or recreated with new filters
+    fn shows(&self, trackhandle: &TrackHandle) -> bool {
+        if self.trackhandle.uuid() == trackhandle.uuid() {
+            return true;
+        }
+        let url = |handle: &TrackHandle| {
+            handle
+                .data::<CustomMetadata>()
+                .aux_metadata
+                .source_url
+                .clone()
+        };
+        url(&self.trackhandle).is_some() && url(&self.trackhandle) == url(trackhandle)
+    }
+}
+
+/// Posts a now playing message whenever the queue moves on, in the channel music was last requested from
+#[derive(Default)]
+pub struct Announcer {
+    channels: std::sync::Mutex<HashMap<serenity::GuildId, serenity::ChannelId>>,
+    announcements: tokio::sync::Mutex<HashMap<serenity::GuildId, Announcement>>,
+}
+
+impl Announcer {
+    pub fn set_channel(&self, guild_id: serenity::GuildId, channel_id: serenity::ChannelId) {
+        self.channels.lock().unwrap().insert(guild_id, channel_id);
+    }
+
+    pub fn channel(&self, guild_id: serenity::GuildId) -> Option<serenity::ChannelId> {
+        self.channels.lock().unwrap().get(&guild_id).copied()
+    }
+
+    /// Edits the guild's message if it's about the same track, otherwise replaces it with a new one
+    /// so it stays at the bottom of the channel
+    async fn announce(
+        &self,
+        ctx: &serenity::Context,
+        guild_id: serenity::GuildId,
+        trackhandle: &TrackHandle,
+        info: TrackState,
+    ) -> eyre::Result<()> {
+        let Some(channel_id) = self.channel(guild_id) else {
+            return Ok(());
+        };
+        let loop_mode = settings::get_guild_settings(ctx, guild_id).await.loop_mode;
+        let embed =
+            queue::now_playing_embed(&trackhandle.data::<CustomMetadata>(), info, loop_mode);
+
+        let mut announcements = self.announcements.lock().await;
+        if let Some(announcement) = announcements.get_mut(&guild_id) {
+            if announcement.channel_id == channel_id && announcement.shows(trackhandle) {
+                announcement.trackhandle = trackhandle.clone();
+                channel_id
+                    .edit_message(
+                        ctx,
+                        announcement.message_id,
+                        serenity::EditMessage::new().embed(embed),
+                    )
+                    .await?;
+                return Ok(());
+            }
+            // it might have been deleted already, that's fine
+            drop(
+                announcement
+                    .channel_id
+                    .delete_message(ctx, announcement.message_id)
+                    .await,
+            );
+        }
+
+        let message = channel_id
+            .send_message(ctx, serenity::CreateMessage::new().embed(embed))
+            .await?;
+        announcements.insert(
+            guild_id,
+            Announcement {
+                channel_id,
+                message_id: message.id,
+                trackhandle: trackhandle.clone(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Marks the guild's message as finished if it's still about `trackhandle`
+    async fn finish(
+        &self,
+        ctx: &serenity::Context,
+        guild_id: serenity::GuildId,
+        trackhandle: &TrackHandle,
+    ) -> eyre::Result<()> {
+        let announcements = self.announcements.lock().await;
+        let Some(announcement) = announcements.get(&guild_id) else {
+            return Ok(());
+        };
+        if announcement.trackhandle.uuid() != trackhandle.uuid() {
+            return Ok(());
+        }
+
+        let metadata = trackhandle.data::<CustomMetadata>();
+        announcement
+            .channel_id
+            .edit_message(
+                ctx,
+                announcement.message_id,
+                serenity::EditMessage::new().embed(
+                    serenity::CreateEmbed::default()
+                        .color(DARK_RED)
+                        .title("Finished playing")
+                        .description(queue::track_title(
+                            metadata.aux_metadata.source_url.clone(),
+                            metadata.aux_metadata.title.clone(),
+                        )),
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+pub struct AnnouncerKey;
+
+impl TypeMapKey for AnnouncerKey {
+    type Value = Arc<Announcer>;
+}
+
+pub async fn get_announcer(ctx: &serenity::Context) -> Arc<Announcer> {
+    let data = ctx.data.read().await;
+    data.get::<AnnouncerKey>().cloned().unwrap()
+}
+
+/// Reacts to a track starting or ending
+struct TrackAnnouncement {
+    ctx: serenity::Context,
+    guild_id: serenity::GuildId,
+    ended: bool,
+}
+
+#[serenity::async_trait]
+impl VoiceEventHandler for TrackAnnouncement {
+    async fn act(&self, event: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(tracks) = event else {
+            return None;
+        };
+        if !settings::get_guild_settings(&self.ctx, self.guild_id)
+            .await
+            .announce_tracks()
+        {
+            return None;
+        }
+
+        let announcer = get_announcer(&self.ctx).await;
+        for (state, trackhandle) in tracks.iter() {
+            let result = if self.ended {
+                announcer
+                    .finish(&self.ctx, self.guild_id, trackhandle)
+                    .await
+            } else {
+                announcer
+                    .announce(&self.ctx, self.guild_id, trackhandle, (*state).clone())
+                    .await
+            };
+            if let Err(e) = result {
+                log::warn!("Can't announce the track: {:?}", e);
+            }
+        }
+
+        None
+    }
+}
+
+/// Announces the track in its guild whenever it starts playing, and marks it finished when it ends
+pub fn add_events(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    trackhandle: &TrackHandle,
+) -> songbird::error::TrackResult<()> {
+    trackhandle.add_event(
+        Event::Track(TrackEvent::Play),
+        TrackAnnouncement {
+            ctx: ctx.clone(),
+            guild_id,
+            ended: false,
+        },
+    )?;
+    trackhandle.add_event(
+        Event::Track(TrackEvent::End),
+        TrackAnnouncement {
+            ctx: ctx.clone(),
+            guild_id,
+            ended: true,
+        },
+    )
+}
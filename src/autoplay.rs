@@ -0,0 +1,180 @@
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
+
+use poise::serenity_prelude as serenity;
+use rand::seq::IndexedRandom;
+use songbird::events::{Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent};
+use songbird::input::Input;
+use songbird::tracks::TrackHandle;
+
+use crate::commands::queueops;
+use crate::history;
+use crate::looping::LoopMode;
+use crate::playlists::{self, PlaylistOwner};
+use crate::settings;
+use crate::utils::{self, CustomMetadata, SavedTrack, SourceKind};
+use crate::ytdl;
+
+/// Shown as the requester of tracks autoplay picked
+const AUTOPLAY_REQUESTER: &str = "📻 Autoplay";
+const RELATED_CANDIDATES: usize = 10;
+/// Tracks that played this recently aren't picked again
+const RECENT_TRACKS: usize = 25;
+
+fn autoplay_track((source, mut metadata): (Input, CustomMetadata)) -> (Input, CustomMetadata) {
+    metadata.requested_by = AUTOPLAY_REQUESTER.to_string();
+    metadata.autoplay = true;
+    (source, metadata)
+}
+
+/// Picks something to play after `seed`, from youtube's related videos or, when those can't be had,
+/// from the guild's history and playlists
+async fn pick(
+    guild_id: serenity::GuildId,
+    http_client: &reqwest::Client,
+    seed: &TrackHandle,
+) -> eyre::Result<Option<(Input, CustomMetadata)>> {
+    let seed = seed.data::<CustomMetadata>();
+    let history = history::load(guild_id).await?;
+    let recent = history
+        .tracks
+        .iter()
+        .take(RECENT_TRACKS)
+        .map(|played| played.track.source.clone())
+        .chain(seed.aux_metadata.source_url.clone())
+        .collect::<HashSet<_>>();
+
+    if let (SourceKind::YoutubeDl, Some(url)) = (seed.kind, &seed.aux_metadata.source_url) {
+        match ytdl::related(url, RELATED_CANDIDATES).await {
+            Ok(related) => {
+                let entry = related.into_iter().flatten().find(|entry| {
+                    entry
+                        .aux_metadata
+                        .source_url
+                        .as_ref()
+                        .is_some_and(|url| !recent.contains(url))
+                });
+                if let Some(entry) = entry {
+                    return Ok(
+                        queueops::ytdl_track(http_client, entry, String::new()).map(autoplay_track)
+                    );
+                }
+            }
+            Err(e) => log::warn!("Can't get related tracks, picking from history: {:?}", e),
+        }
+    }
+
+    let saved = playlists::load(PlaylistOwner::Guild(guild_id)).await?;
+    let candidates = history
+        .tracks
+        .iter()
+        .map(|played| played.track.clone())
+        .chain(
+            saved
+                .playlists
+                .values()
+                .flat_map(|playlist| playlist.tracks.iter().cloned()),
+        )
+        .filter(|track| !recent.contains(&track.source))
+        .collect::<Vec<_>>();
+    // tracks from the same artist or channel go better with the last one
+    let similar = candidates
+        .iter()
+        .filter(|track| {
+            track.metadata.channel.is_some() && track.metadata.channel == seed.aux_metadata.channel
+        })
+        .cloned()
+        .collect::<Vec<SavedTrack>>();
+    let pool = if similar.is_empty() {
+        &candidates
+    } else {
+        &similar
+    };
+
+    Ok(pool
+        .choose(&mut rand::rng())
+        .cloned()
+        .map(|track| autoplay_track(track.into_source(http_client.clone()))))
+}
+
+/// Queues something new once the last track of a guild's queue ends, if the guild has autoplay on
+struct Autoplay {
+    ctx: serenity::Context,
+    guild_id: serenity::GuildId,
+}
+
+impl Autoplay {
+    async fn refill(&self, ended: &TrackHandle) -> eyre::Result<()> {
+        let manager = songbird::get(&self.ctx).await.unwrap().clone();
+        // the bot left, there's nobody to play to
+        let Some(call) = manager.get(self.guild_id) else {
+            return Ok(());
+        };
+        {
+            let handler = call.lock().await;
+            if handler.current_channel().is_none() {
+                return Ok(());
+            }
+            let queue = handler.queue().current_queue();
+            if queue.iter().any(|handle| handle.uuid() != ended.uuid()) {
+                return Ok(());
+            }
+        }
+
+        let http_client = {
+            let data = self.ctx.data.read().await;
+            data.get::<utils::HttpKey>().cloned().unwrap()
+        };
+        let Some(track) = pick(self.guild_id, &http_client, ended).await? else {
+            return Ok(());
+        };
+        queueops::enqueue(&self.ctx, self.guild_id, vec![track], false, false).await?;
+
+        Ok(())
+    }
+}
+
+#[serenity::async_trait]
+impl VoiceEventHandler for Autoplay {
+    async fn act(&self, event: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(tracks) = event else {
+            return None;
+        };
+        let guild_settings = settings::get_guild_settings(&self.ctx, self.guild_id).await;
+        // a looping queue never runs dry
+        if !guild_settings.autoplay() || guild_settings.loop_mode == LoopMode::Queue {
+            return None;
+        }
+
+        for (_, trackhandle) in tracks.iter() {
+            // the queue was emptied on purpose
+            if trackhandle
+                .data::<CustomMetadata>()
+                .discarded
+                .load(Ordering::Relaxed)
+            {
+                continue;
+            }
+            if let Err(e) = self.refill(trackhandle).await {
+                log::error!("Can't autoplay: {:?}", e);
+            }
+        }
+
+        None
+    }
+}
+
+/// Keeps the music going after the track if it's the last one in its guild's queue by then
+pub fn add_events(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    trackhandle: &TrackHandle,
+) -> songbird::error::TrackResult<()> {
+    trackhandle.add_event(
+        Event::Track(TrackEvent::End),
+        Autoplay {
+            ctx: ctx.clone(),
+            guild_id,
+        },
+    )
+}
@@ -1,3 +1,12 @@
+pub mod admin;
+pub mod filter;
+pub mod history;
+pub mod library;
+pub mod lyrics;
+pub mod permissions;
+pub mod playlist;
 pub mod queue;
 pub mod queueops;
+pub mod search;
+pub mod settings;
 pub mod trackops;
@@ -0,0 +1,43 @@
+use crate::reload;
+use crate::utils;
+use crate::Context;
+use crate::Error;
+
+/// Only lets the owners from config.toml through (also runs for subcommands)
+async fn is_owner(ctx: Context<'_>) -> Result<bool, Error> {
+    let is_owner = utils::is_owner(&ctx);
+
+    if !is_owner {
+        ctx.say("Only my owners can do that!").await?;
+    }
+
+    Ok(is_owner)
+}
+
+/// Manages the bot itself (owners only)
+#[poise::command(
+    slash_command,
+    subcommands("reload"),
+    subcommand_required,
+    check = "is_owner"
+)]
+pub async fn admin(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Reads config.toml again without restarting
+#[poise::command(slash_command, ephemeral)]
+pub async fn reload(ctx: Context<'_>) -> Result<(), Error> {
+    if let Err(e) = reload::reload(&ctx.framework().shard_manager()).await {
+        ctx.say(format!(
+            "Couldn't reload the config, still using the old one:\n```{:?}```",
+            e
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    ctx.say("Reloaded the config!").await?;
+
+    Ok(())
+}
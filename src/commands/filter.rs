@@ -0,0 +1,226 @@
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+
+use poise::serenity_prelude as serenity;
+use poise::ChoiceParameter;
+use songbird::tracks::{LoopState, PlayMode};
+
+use crate::commands::queueops;
+use crate::effects::{self, Filter};
+use crate::permissions;
+use crate::settings;
+use crate::utils;
+use crate::utils::{CustomMetadata, SavedTrack};
+use crate::Context;
+use crate::Error;
+
+/// Recreates the current track with the guild's filters, picking up where it left off
+async fn refilter_current(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+) -> Result<(), Error> {
+    let manager = songbird::get(ctx).await.unwrap().clone();
+    let Some(call) = manager.get(guild_id) else {
+        return Ok(());
+    };
+    let Some(current) = call.lock().await.queue().current() else {
+        return Ok(());
+    };
+    let Some(saved) = SavedTrack::from_handle(&current) else {
+        return Ok(());
+    };
+    let Ok(info) = current.get_info().await else {
+        // it just ended, the next track gets the filters anyway
+        return Ok(());
+    };
+    let metadata = current.data::<CustomMetadata>();
+    let position = effects::source_position(&metadata, info.position);
+
+    let http_client = {
+        let data = ctx.data.read().await;
+        data.get::<utils::HttpKey>().cloned().unwrap()
+    };
+    let handles = queueops::enqueue(
+        ctx,
+        guild_id,
+        vec![saved.into_source(http_client.clone())],
+        true,
+        false,
+    )
+    .await?;
+    let Some(trackhandle) = handles.first() else {
+        return Ok(());
+    };
+
+    // the old track got pushed back behind the new one
+    call.lock().await.queue().modify_queue(|queue| {
+        queue.retain(|handle| handle.uuid() != current.uuid());
+    });
+    metadata.discarded.store(true, Ordering::Relaxed);
+    if let Err(e) = current.stop() {
+        log::error!("{:?}", e);
+    }
+
+    match info.loops {
+        LoopState::Infinite => trackhandle.enable_loop()?,
+        LoopState::Finite(nonmax::NonMaxU32::ZERO) => {}
+        LoopState::Finite(num) => trackhandle.loop_for(num)?,
+    }
+    let new_metadata = trackhandle.data::<CustomMetadata>();
+    if !position.is_zero() && !new_metadata.live {
+        // the track isn't made playable yet, so don't wait on it
+        drop(trackhandle.seek(effects::track_position(&new_metadata, position)));
+    }
+    if info.playing == PlayMode::Pause {
+        trackhandle.pause()?;
+    }
+
+    Ok(())
+}
+
+/// Applies audio effects to everything played in this server
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("set", "clear", "list"),
+    subcommand_required
+)]
+pub async fn filter(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Turns an effect on, for the current track and every track after it
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn set(
+    ctx: Context<'_>,
+    #[description = "Effect to turn on"] filter: Filter,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+
+    let added = settings::get_settings_store(ctx.serenity_context())
+        .await
+        .modify(guild_id, |guild_settings| {
+            if guild_settings.filters.contains(&filter) {
+                return false;
+            }
+            guild_settings.filters.push(filter);
+            guild_settings.filters.sort();
+            true
+        })
+        .await?;
+
+    if !added {
+        ctx.say(format!("**{}** is already on!", filter.name()))
+            .await?;
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+    refilter_current(ctx.serenity_context(), guild_id).await?;
+
+    ctx.say(format!("Turned on **{}**!", filter.name())).await?;
+
+    Ok(())
+}
+
+/// Turns an effect off, or every effect if none is picked
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn clear(
+    ctx: Context<'_>,
+    #[description = "Effect to turn off, defaults to all of them"] filter: Option<Filter>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+
+    let removed = settings::get_settings_store(ctx.serenity_context())
+        .await
+        .modify(guild_id, |guild_settings| {
+            let before = guild_settings.filters.len();
+            match filter {
+                Some(filter) => guild_settings.filters.retain(|item| *item != filter),
+                None => guild_settings.filters.clear(),
+            }
+            before != guild_settings.filters.len()
+        })
+        .await?;
+
+    if !removed {
+        ctx.say(match filter {
+            Some(filter) => format!("**{}** isn't on!", filter.name()),
+            None => "There are no effects on!".to_string(),
+        })
+        .await?;
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+    refilter_current(ctx.serenity_context(), guild_id).await?;
+
+    ctx.say(match filter {
+        Some(filter) => format!("Turned off **{}**!", filter.name()),
+        None => "Turned off every effect!".to_string(),
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Lists the available effects, and which ones are on
+#[poise::command(slash_command)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+
+    let active = settings::get_guild_settings(ctx.serenity_context(), guild_id)
+        .await
+        .filters;
+
+    let description = Filter::all()
+        .iter()
+        .fold(String::new(), |mut string, filter| {
+            _ = writeln!(
+                &mut string,
+                "{} **{}**: {}",
+                if active.contains(filter) {
+                    "✅"
+                } else {
+                    "▫️"
+                },
+                filter.name(),
+                filter.describe()
+            );
+            string
+        });
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            poise::serenity_prelude::CreateEmbed::default()
+                .title("Effects")
+                .description(description)
+                .footer(poise::serenity_prelude::CreateEmbedFooter::new(
+                    if active.is_empty() {
+                        "No effects on".to_string()
+                    } else {
+                        format!(
+                            "On: {}",
+                            active
+                                .iter()
+                                .map(|filter| filter.name())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    },
+                )),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use std::fmt::Write;
+use std::time::UNIX_EPOCH;
+
+use poise::serenity_prelude as serenity;
+
+use crate::commands::queue::track_title;
+use crate::commands::queueops;
+use crate::history;
+use crate::limits;
+use crate::permissions;
+use crate::utils;
+use crate::Context;
+use crate::Error;
+
+const PAGE_SIZE: usize = 10;
+
+/// Views the tracks that played in this server, newest first
+#[poise::command(slash_command, guild_only)]
+pub async fn history(
+    ctx: Context<'_>,
+    #[description = "Page number"]
+    #[min = 1]
+    page: Option<usize>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+
+    let history = history::load(guild_id).await?;
+    if history.tracks.is_empty() {
+        ctx.say("Nothing has been played yet!").await?;
+        return Ok(());
+    }
+
+    let pages = history.tracks.len().div_ceil(PAGE_SIZE);
+    let page = page.unwrap_or(1).clamp(1, pages);
+
+    let description = history
+        .tracks
+        .iter()
+        .enumerate()
+        .skip((page - 1) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .fold(String::new(), |mut string, (index, played)| {
+            let played_at = played
+                .played_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            _ = writeln!(
+                &mut string,
+                "`{}.` {} `{}` {} <t:{played_at}:R>",
+                index + 1,
+                track_title(
+                    Some(played.track.source.clone()),
+                    played.track.metadata.title.clone()
+                ),
+                utils::human_print_time(played.played_for),
+                played.track.requested_by
+            );
+            string
+        });
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title("Recently played")
+                .description(description)
+                .footer(serenity::CreateEmbedFooter::new(format!(
+                    "Page {page} out of {pages}"
+                ))),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Plays the last track that played again, in front of the queue
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn previous(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = utils::join_author_channel(&ctx).await? else {
+        return Ok(());
+    };
+
+    let Some(played) = history::load(guild_id).await?.tracks.pop_front() else {
+        ctx.say("Nothing has been played yet!").await?;
+        return Ok(());
+    };
+
+    let http_client = {
+        let data = ctx.serenity_context().data.read().await;
+        data.get::<utils::HttpKey>().cloned().unwrap()
+    };
+    let played_at = played.played_at;
+    let mut track = played.track;
+    track.requested_by = format!("<@{}>", ctx.author().id);
+    let title = track
+        .metadata
+        .title
+        .clone()
+        .unwrap_or("Untitled".to_string());
+
+    let (tracks, refused) =
+        limits::apply(&ctx, guild_id, vec![track.into_source(http_client.clone())]).await?;
+    if let Some(refused) = refused {
+        ctx.say(refused).await?;
+        return Ok(());
+    }
+
+    queueops::enqueue(ctx.serenity_context(), guild_id, tracks, true, false).await?;
+    // it's recorded again once it ends, so going back twice goes further back
+    history::modify(guild_id, |history| {
+        history.tracks.retain(|other| other.played_at != played_at)
+    })
+    .await?;
+
+    ctx.say(format!("Got it!. Playing **{title}** again"))
+        .await?;
+
+    Ok(())
+}
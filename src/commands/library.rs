@@ -0,0 +1,198 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::commands::queueops;
+use crate::library::{self, Library, LibraryTrack};
+use crate::limits;
+use crate::permissions;
+use crate::utils;
+use crate::Context;
+use crate::Error;
+
+const SEARCH_RESULTS: usize = 10;
+
+async fn get_library(ctx: &Context<'_>) -> Result<Option<Arc<Library>>, Error> {
+    let library = {
+        let data = ctx.serenity_context().data.read().await;
+        data.get::<library::LibraryKey>().cloned()
+    };
+    if library.is_none() {
+        ctx.say("There's no music library set up!").await?;
+    }
+    Ok(library)
+}
+
+fn track_line(index: usize, track: &LibraryTrack) -> String {
+    let duration = track
+        .duration
+        .map(utils::human_print_time)
+        .unwrap_or_default();
+    format!(
+        "`{}.` **{}** - {} ({}) `{duration}`",
+        index + 1,
+        track.title(),
+        track.artist.as_deref().unwrap_or("Unknown artist"),
+        track.album.as_deref().unwrap_or("Unknown album"),
+    )
+}
+
+/// Enqueues library tracks, and shows the first one's cover if it has one
+async fn enqueue_tracks(
+    ctx: Context<'_>,
+    tracks: Vec<LibraryTrack>,
+    name: String,
+) -> Result<(), Error> {
+    let Some(guild_id) = utils::join_author_channel(&ctx).await? else {
+        return Ok(());
+    };
+
+    let id = format!("<@{}>", ctx.author().id);
+    let cover_path = tracks[0].path.clone();
+    let sources = tracks
+        .into_iter()
+        .map(|track| track.into_source(id.clone()))
+        .collect();
+    let (sources, refused) = limits::apply(&ctx, guild_id, sources).await?;
+    if let (true, Some(refused)) = (sources.is_empty(), &refused) {
+        ctx.say(refused).await?;
+        return Ok(());
+    }
+    let added = queueops::enqueue(ctx.serenity_context(), guild_id, sources, false, false)
+        .await?
+        .len();
+
+    let message = match added {
+        1 => format!(
+            "Got it!. Added **{name}** to the queue{}",
+            limits::footnote(refused)
+        ),
+        added => format!(
+            "Got it!. Added **{added}** tracks from **{name}** to the queue{}",
+            limits::footnote(refused)
+        ),
+    };
+
+    let cover = tokio::task::spawn_blocking(move || library::read_cover(&cover_path)).await?;
+    let reply = match cover {
+        Some((data, filename)) => poise::CreateReply::default()
+            .embed(
+                poise::serenity_prelude::CreateEmbed::default()
+                    .description(message)
+                    .thumbnail(format!("attachment://{filename}")),
+            )
+            .attachment(poise::serenity_prelude::CreateAttachment::bytes(
+                data, filename,
+            )),
+        None => poise::CreateReply::default().content(message),
+    };
+    ctx.send(reply).await?;
+
+    Ok(())
+}
+
+/// Browses and plays music from the bot's local library
+#[poise::command(
+    slash_command,
+    subcommands("search", "play", "album", "artist"),
+    subcommand_required
+)]
+pub async fn library(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Searches the library by title, artist or album
+#[poise::command(slash_command)]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "Words to look for"] query: String,
+) -> Result<(), Error> {
+    let Some(library) = get_library(&ctx).await? else {
+        return Ok(());
+    };
+
+    let results = library.search(&query);
+    if results.is_empty() {
+        ctx.say("Couldn't find anything like that!").await?;
+        return Ok(());
+    }
+
+    let description = results.iter().take(SEARCH_RESULTS).enumerate().fold(
+        String::new(),
+        |mut string, (index, track)| {
+            _ = writeln!(&mut string, "{}", track_line(index, track));
+            string
+        },
+    );
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            poise::serenity_prelude::CreateEmbed::default()
+                .title(format!("Library results for \"{query}\""))
+                .description(description)
+                .footer(poise::serenity_prelude::CreateEmbedFooter::new(format!(
+                    "{} matches",
+                    results.len()
+                ))),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Queues the best matching track from the library
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn play(
+    ctx: Context<'_>,
+    #[description = "Title, artist or album to look for"] query: String,
+) -> Result<(), Error> {
+    let Some(library) = get_library(&ctx).await? else {
+        return Ok(());
+    };
+
+    let Some(track) = library.search(&query).into_iter().next() else {
+        ctx.say("Couldn't find anything like that!").await?;
+        return Ok(());
+    };
+
+    let name = track.title();
+    enqueue_tracks(ctx, vec![track], name).await
+}
+
+/// Queues a whole album from the library
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn album(
+    ctx: Context<'_>,
+    #[description = "Album name"] name: String,
+) -> Result<(), Error> {
+    let Some(library) = get_library(&ctx).await? else {
+        return Ok(());
+    };
+
+    let tracks = library.album(&name);
+    let Some(album) = tracks.first().and_then(|track| track.album.clone()) else {
+        ctx.say("Couldn't find that album!").await?;
+        return Ok(());
+    };
+
+    enqueue_tracks(ctx, tracks, album).await
+}
+
+/// Queues everything by an artist from the library
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn artist(
+    ctx: Context<'_>,
+    #[description = "Artist name"] name: String,
+) -> Result<(), Error> {
+    let Some(library) = get_library(&ctx).await? else {
+        return Ok(());
+    };
+
+    let tracks = library.artist(&name);
+    let Some(artist) = tracks.first().and_then(|track| track.artist.clone()) else {
+        ctx.say("Couldn't find that artist!").await?;
+        return Ok(());
+    };
+
+    enqueue_tracks(ctx, tracks, artist).await
+}
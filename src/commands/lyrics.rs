@@ -0,0 +1,168 @@
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude as serenity;
+
+use crate::effects;
+use crate::lyrics::{self, Lyrics, LyricsQuery};
+use crate::permissions;
+use crate::utils;
+use crate::utils::CustomMetadata;
+use crate::Context;
+use crate::Error;
+
+const LIVE_INTERVAL: Duration = Duration::from_secs(1);
+// discord stops accepting edits to a reply after 15 minutes
+const LIVE_TIMEOUT: Duration = Duration::from_secs(14 * 60);
+const LINES_BEFORE: usize = 2;
+const LINES_AFTER: usize = 4;
+// discord's limit for embed descriptions
+const DESCRIPTION_LENGTH: usize = 4096;
+
+fn lyrics_embed(title: &str, description: &str) -> serenity::CreateEmbed {
+    serenity::CreateEmbed::default()
+        .title(utils::truncate(&format!("Lyrics for {title}"), 256))
+        .description(utils::truncate(description, DESCRIPTION_LENGTH))
+}
+
+/// The lines around the one being sung, which is in bold
+fn live_window(lines: &[(Duration, String)], current: Option<usize>) -> String {
+    // instrumental parts show up as empty lines
+    let line = |text: &str| {
+        if text.is_empty() {
+            "♪".to_string()
+        } else {
+            text.to_string()
+        }
+    };
+    let Some(current) = current else {
+        return lines
+            .iter()
+            .take(LINES_AFTER)
+            .map(|(_, text)| line(text))
+            .collect::<Vec<_>>()
+            .join("\n");
+    };
+    lines
+        .iter()
+        .enumerate()
+        .skip(current.saturating_sub(LINES_BEFORE))
+        .take(LINES_BEFORE + 1 + LINES_AFTER)
+        .map(|(index, (_, text))| {
+            if index == current {
+                format!("**{}**", line(text))
+            } else {
+                line(text)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Shows the lyrics of the current track, following along as it plays if they're timed
+///
+/// Only the newest live lyrics in a server follow along, the ones before it stop
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn lyrics(
+    ctx: Context<'_>,
+    #[description = "Follow along with the track, if its lyrics are timed"]
+    #[flag]
+    live: bool,
+) -> Result<(), Error> {
+    let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
+        return Ok(());
+    };
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let Some(trackhandle) = handler_lock.lock().await.queue().current() else {
+        ctx.say("Nothing is queued right now!").await?;
+        return Ok(());
+    };
+    let metadata = trackhandle.data::<CustomMetadata>();
+    let title = metadata
+        .aux_metadata
+        .title
+        .clone()
+        .unwrap_or("Untitled".to_string());
+
+    ctx.defer().await?;
+    let finder = lyrics::get_lyrics_finder(ctx.serenity_context()).await;
+    let Some(found) = finder.find(&LyricsQuery::new(&metadata)).await else {
+        ctx.say(format!("Couldn't find lyrics for **{title}**!"))
+            .await?;
+        return Ok(());
+    };
+
+    let lines = match (&found, live) {
+        (Lyrics::Synced(lines), true) => lines,
+        (Lyrics::Plain(_), true) => {
+            ctx.send(poise::CreateReply::default().embed(
+                lyrics_embed(&title, &found.text()).footer(serenity::CreateEmbedFooter::new(
+                    "These lyrics aren't timed, so they can't follow along",
+                )),
+            ))
+            .await?;
+            return Ok(());
+        }
+        (_, false) => {
+            ctx.send(poise::CreateReply::default().embed(lyrics_embed(&title, &found.text())))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let current_line = |position: Duration| lines.iter().rposition(|(time, _)| *time <= position);
+    let position = match trackhandle.get_info().await {
+        Ok(info) => effects::source_position(&metadata, info.position),
+        Err(_) => Duration::ZERO,
+    };
+    let mut shown = current_line(position);
+    let reply = ctx
+        .send(poise::CreateReply::default().embed(lyrics_embed(&title, &live_window(lines, shown))))
+        .await?;
+
+    let live_lyrics = lyrics::get_live_lyrics(ctx.serenity_context()).await;
+    let session = live_lyrics.start(guild_id);
+    let started = Instant::now();
+    let mut interval = tokio::time::interval(LIVE_INTERVAL);
+    while started.elapsed() < LIVE_TIMEOUT {
+        interval.tick().await;
+
+        // someone asked for them again, the newer message follows along instead
+        if !live_lyrics.is_current(guild_id, session) {
+            break;
+        }
+
+        // the lyrics are about this track only, so they stop once something else plays
+        let current = handler_lock.lock().await.queue().current();
+        if current.map(|handle| handle.uuid()) != Some(trackhandle.uuid()) {
+            break;
+        }
+        let Ok(info) = trackhandle.get_info().await else {
+            break;
+        };
+
+        let line = current_line(effects::source_position(&metadata, info.position));
+        if line != shown {
+            shown = line;
+            reply
+                .edit(
+                    ctx,
+                    poise::CreateReply::default()
+                        .embed(lyrics_embed(&title, &live_window(lines, shown))),
+                )
+                .await?;
+        }
+    }
+    live_lyrics.end(guild_id, session);
+
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default().embed(lyrics_embed(&title, &found.text())),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use std::fmt::Write;
+
+use poise::ChoiceParameter;
+
+use crate::permissions::{self, PermissionLevel};
+use crate::settings;
+use crate::utils;
+use crate::Context;
+use crate::Error;
+
+/// Only lets members with the Manage Server permission, or bot owners through (also runs for subcommands)
+async fn can_manage_permissions(ctx: Context<'_>) -> Result<bool, Error> {
+    let can_manage = utils::can_manage_guild(&ctx).await;
+
+    if !can_manage {
+        ctx.say("You need the Manage Server permission to change who can use my commands!")
+            .await?;
+    }
+
+    Ok(can_manage)
+}
+
+async fn autocomplete_command<'a>(
+    _ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    permissions::COMMANDS
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .filter(move |name| name.contains(partial))
+}
+
+/// Views or changes who can use which command in this server
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("view", "set", "reset"),
+    subcommand_required,
+    check = "can_manage_permissions"
+)]
+pub async fn permissions(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Views the permission level of every command
+#[poise::command(slash_command)]
+pub async fn view(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+
+    let guild_settings = settings::get_guild_settings(ctx.serenity_context(), guild_id).await;
+
+    let description =
+        permissions::COMMANDS
+            .iter()
+            .fold(String::new(), |mut string, (command, _)| {
+                _ = writeln!(
+                    &mut string,
+                    "`/{command}`: **{}**{}",
+                    permissions::required_level(&guild_settings, command).name(),
+                    if guild_settings.permissions.contains_key(*command) {
+                        ""
+                    } else {
+                        " (default)"
+                    }
+                );
+                string
+            });
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            poise::serenity_prelude::CreateEmbed::default()
+                .title("Command permissions")
+                .description(description)
+                .footer(poise::serenity_prelude::CreateEmbedFooter::new(
+                    match guild_settings.dj_role {
+                        Some(_) => "Defaults apply because a DJ role is set",
+                        None => "Set a DJ role with /settings to turn on the defaults",
+                    },
+                )),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Changes who can use a command
+#[poise::command(slash_command)]
+pub async fn set(
+    ctx: Context<'_>,
+    #[description = "Command to change"]
+    #[autocomplete = "autocomplete_command"]
+    command: String,
+    #[description = "Who can use it"] level: PermissionLevel,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+
+    let command = command.trim_start_matches('/').to_string();
+    if !permissions::is_known_command(&command) {
+        ctx.say(format!("`/{command}` doesn't have a permission level!"))
+            .await?;
+        return Ok(());
+    }
+
+    settings::get_settings_store(ctx.serenity_context())
+        .await
+        .modify(guild_id, |guild_settings| {
+            guild_settings.permissions.insert(command.clone(), level)
+        })
+        .await?;
+
+    ctx.say(format!(
+        "`/{command}` can now be used by **{}**!",
+        level.name()
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Resets a command, or every command, back to its default level
+#[poise::command(slash_command)]
+pub async fn reset(
+    ctx: Context<'_>,
+    #[description = "Command to reset, resets everything if unset"]
+    #[autocomplete = "autocomplete_command"]
+    command: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+
+    let command = command.map(|command| command.trim_start_matches('/').to_string());
+
+    settings::get_settings_store(ctx.serenity_context())
+        .await
+        .modify(guild_id, |guild_settings| match &command {
+            Some(command) => drop(guild_settings.permissions.remove(command)),
+            None => guild_settings.permissions.clear(),
+        })
+        .await?;
+
+    match command {
+        Some(command) => ctx.say(format!("Reset `/{command}`!")).await?,
+        None => ctx.say("Reset every command!").await?,
+    };
+
+    Ok(())
+}
@@ -0,0 +1,456 @@
+use std::fmt::Write;
+
+use poise::ChoiceParameter;
+
+use crate::commands::queueops;
+use crate::limits;
+use crate::permissions;
+use crate::playlists::{self, Playlist, PlaylistOwner, PlaylistScope};
+use crate::suggestions;
+use crate::utils;
+use crate::utils::SavedTrack;
+use crate::Context;
+use crate::Error;
+
+const SHOWN_TRACKS: usize = 20;
+
+#[derive(poise::ChoiceParameter, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddSource {
+    #[name = "current"]
+    Current,
+    #[name = "queue"]
+    Queue,
+}
+
+/// Picks whose playlists to use, tells the author if server playlists aren't available
+async fn get_owner(
+    ctx: &Context<'_>,
+    scope: Option<PlaylistScope>,
+) -> Result<Option<PlaylistOwner>, Error> {
+    match scope.unwrap_or(PlaylistScope::Personal) {
+        PlaylistScope::Personal => Ok(Some(PlaylistOwner::User(ctx.author().id))),
+        PlaylistScope::Server => match ctx.guild_id() {
+            Some(guild_id) => Ok(Some(PlaylistOwner::Guild(guild_id))),
+            None => {
+                ctx.say("Server playlists only exist in a server!").await?;
+                Ok(None)
+            }
+        },
+    }
+}
+
+/// Server playlists can only be changed by whoever made them, or by people who can manage the server
+async fn can_edit(ctx: &Context<'_>, owner: PlaylistOwner, playlist: &Playlist) -> bool {
+    match owner {
+        PlaylistOwner::User(_) => true,
+        PlaylistOwner::Guild(_) => {
+            playlist.created_by == ctx.author().id.get() || utils::can_manage_guild(ctx).await
+        }
+    }
+}
+
+/// Looks a playlist up, tells the author if it doesn't exist
+async fn get_playlist(
+    ctx: &Context<'_>,
+    owner: PlaylistOwner,
+    name: &str,
+) -> Result<Option<Playlist>, Error> {
+    let playlist = playlists::load(owner).await?.get(name).cloned();
+    if playlist.is_none() {
+        ctx.say(format!("There's no playlist called **{name}**!"))
+            .await?;
+    }
+    Ok(playlist)
+}
+
+/// Looks a playlist up for /play, the author's own playlists come before the server's
+pub async fn find_saved(ctx: &Context<'_>, name: &str) -> Result<Option<Playlist>, Error> {
+    if let Some(playlist) = playlists::load(PlaylistOwner::User(ctx.author().id))
+        .await?
+        .get(name)
+    {
+        return Ok(Some(playlist.clone()));
+    }
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(None);
+    };
+    Ok(playlists::load(PlaylistOwner::Guild(guild_id))
+        .await?
+        .get(name)
+        .cloned())
+}
+
+/// Queues every track of a saved playlist as requested by the author, within the server's limits
+///
+/// Returns how many were added, and why the others weren't if some were left out
+pub async fn enqueue_playlist(
+    ctx: &Context<'_>,
+    guild_id: poise::serenity_prelude::GuildId,
+    playlist: Playlist,
+    shuffle: bool,
+) -> Result<(usize, Option<String>), Error> {
+    let http_client = {
+        let data = ctx.serenity_context().data.read().await;
+        data.get::<utils::HttpKey>().cloned().unwrap()
+    };
+
+    let mut tracks = playlist.tracks;
+    if shuffle {
+        use rand::seq::SliceRandom;
+        tracks.shuffle(&mut rand::rng());
+    }
+
+    let id = format!("<@{}>", ctx.author().id);
+    let sources = tracks
+        .into_iter()
+        .map(|track| {
+            let (source, mut metadata) = track.into_source(http_client.clone());
+            metadata.requested_by = id.clone();
+            (source, metadata)
+        })
+        .collect();
+    let (sources, refused) = limits::apply(ctx, guild_id, sources).await?;
+    let handles =
+        queueops::enqueue(ctx.serenity_context(), guild_id, sources, false, false).await?;
+    suggestions::get_suggestions(ctx.serenity_context())
+        .await
+        .record_queued(guild_id, &handles);
+
+    Ok((handles.len(), refused))
+}
+
+/// Saves tracks into playlists for later
+#[poise::command(
+    slash_command,
+    subcommands("create", "add", "remove", "list", "show", "load", "delete"),
+    subcommand_required
+)]
+pub async fn playlist(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Creates an empty playlist
+#[poise::command(slash_command)]
+pub async fn create(
+    ctx: Context<'_>,
+    #[description = "Playlist name"] name: String,
+    #[description = "Personal or server, defaults to personal"] scope: Option<PlaylistScope>,
+) -> Result<(), Error> {
+    let Some(owner) = get_owner(&ctx, scope).await? else {
+        return Ok(());
+    };
+
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        ctx.say("The playlist needs a name!").await?;
+        return Ok(());
+    }
+
+    let author_id = ctx.author().id.get();
+    let created = playlists::modify(owner, |playlists| {
+        if playlists.get(&name).is_some() {
+            return false;
+        }
+        playlists.insert(Playlist {
+            name: name.clone(),
+            created_by: author_id,
+            tracks: Vec::new(),
+        });
+        true
+    })
+    .await?;
+
+    if !created {
+        ctx.say(format!("There's already a playlist called **{name}**!"))
+            .await?;
+        return Ok(());
+    }
+
+    ctx.say(format!("Created **{name}**!")).await?;
+
+    Ok(())
+}
+
+/// Adds the current track or the whole queue to a playlist
+#[poise::command(slash_command)]
+pub async fn add(
+    ctx: Context<'_>,
+    #[description = "Playlist name"] name: String,
+    #[description = "What to add"] source: AddSource,
+    #[description = "Personal or server, defaults to personal"] scope: Option<PlaylistScope>,
+) -> Result<(), Error> {
+    let Some(owner) = get_owner(&ctx, scope).await? else {
+        return Ok(());
+    };
+    let Some(playlist) = get_playlist(&ctx, owner, &name).await? else {
+        return Ok(());
+    };
+    if !can_edit(&ctx, owner, &playlist).await {
+        ctx.say("Only the person who made this playlist can change it!")
+            .await?;
+        return Ok(());
+    }
+
+    let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
+        return Ok(());
+    };
+
+    let handles = {
+        let guard = handler_lock.lock().await;
+        match source {
+            AddSource::Current => guard.queue().current().into_iter().collect(),
+            AddSource::Queue => guard.queue().current_queue(),
+        }
+    };
+
+    let tracks = handles
+        .iter()
+        .filter_map(SavedTrack::from_handle)
+        .collect::<Vec<_>>();
+    if tracks.is_empty() {
+        ctx.say("Nothing is queued right now!").await?;
+        return Ok(());
+    }
+
+    let added = tracks.len();
+    playlists::modify(owner, |playlists| {
+        if let Some(playlist) = playlists.get_mut(&name) {
+            playlist.tracks.extend(tracks);
+        }
+    })
+    .await?;
+
+    ctx.say(format!(
+        "Added **{added}** track{} to **{}**!",
+        if added == 1 { "" } else { "s" },
+        playlist.name
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Removes tracks from a playlist
+#[poise::command(slash_command)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Playlist name"] name: String,
+    #[description = "Index of the track to remove"] index: usize,
+    #[description = "Number of additional tracks to remove, defaults to 0"] size: Option<usize>,
+    #[description = "Personal or server, defaults to personal"] scope: Option<PlaylistScope>,
+) -> Result<(), Error> {
+    let Some(owner) = get_owner(&ctx, scope).await? else {
+        return Ok(());
+    };
+    let Some(playlist) = get_playlist(&ctx, owner, &name).await? else {
+        return Ok(());
+    };
+    if !can_edit(&ctx, owner, &playlist).await {
+        ctx.say("Only the person who made this playlist can change it!")
+            .await?;
+        return Ok(());
+    }
+
+    if index == 0 || index > playlist.tracks.len() {
+        ctx.say(format!(
+            "**{}** only has {} tracks!",
+            playlist.name,
+            playlist.tracks.len()
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let index = index - 1;
+    let size = size.unwrap_or(0);
+
+    let removed = playlists::modify(owner, |playlists| {
+        let Some(playlist) = playlists.get_mut(&name) else {
+            return 0;
+        };
+        let end = (index + size + 1).min(playlist.tracks.len());
+        playlist.tracks.drain(index..end).count()
+    })
+    .await?;
+
+    ctx.say(format!(
+        "Removed **{removed}** track{} from **{}**!",
+        if removed == 1 { "" } else { "s" },
+        playlist.name
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Lists saved playlists
+#[poise::command(slash_command)]
+pub async fn list(
+    ctx: Context<'_>,
+    #[description = "Personal or server, defaults to personal"] scope: Option<PlaylistScope>,
+) -> Result<(), Error> {
+    let Some(owner) = get_owner(&ctx, scope).await? else {
+        return Ok(());
+    };
+
+    let playlists = playlists::load(owner).await?;
+    if playlists.playlists.is_empty() {
+        ctx.say("There are no playlists yet!").await?;
+        return Ok(());
+    }
+
+    let description = playlists
+        .playlists
+        .values()
+        .fold(String::new(), |mut string, playlist| {
+            _ = writeln!(
+                &mut string,
+                "**{}** - {} tracks",
+                playlist.name,
+                playlist.tracks.len()
+            );
+            string
+        });
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            poise::serenity_prelude::CreateEmbed::default()
+                .title(format!(
+                    "{} playlists",
+                    match scope.unwrap_or(PlaylistScope::Personal) {
+                        PlaylistScope::Personal => "Your",
+                        PlaylistScope::Server => "Server",
+                    }
+                ))
+                .description(description),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Shows the tracks in a playlist
+#[poise::command(slash_command)]
+pub async fn show(
+    ctx: Context<'_>,
+    #[description = "Playlist name"] name: String,
+    #[description = "Personal or server, defaults to personal"] scope: Option<PlaylistScope>,
+) -> Result<(), Error> {
+    let Some(owner) = get_owner(&ctx, scope).await? else {
+        return Ok(());
+    };
+    let Some(playlist) = get_playlist(&ctx, owner, &name).await? else {
+        return Ok(());
+    };
+
+    let mut description = playlist.tracks.iter().take(SHOWN_TRACKS).enumerate().fold(
+        String::new(),
+        |mut string, (index, track)| {
+            let duration = track
+                .metadata
+                .duration
+                .map(utils::human_print_time)
+                .unwrap_or_default();
+            _ = writeln!(
+                &mut string,
+                "`{}.` {} `{duration}`",
+                index + 1,
+                track.metadata.title.as_deref().unwrap_or("Untitled")
+            );
+            string
+        },
+    );
+    if playlist.tracks.len() > SHOWN_TRACKS {
+        _ = write!(
+            &mut description,
+            "...and {} more",
+            playlist.tracks.len() - SHOWN_TRACKS
+        );
+    }
+    if playlist.tracks.is_empty() {
+        description = "This playlist is empty!".to_string();
+    }
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            poise::serenity_prelude::CreateEmbed::default()
+                .title(playlist.name)
+                .description(description)
+                .footer(poise::serenity_prelude::CreateEmbedFooter::new(format!(
+                    "{} playlist, {} tracks",
+                    scope.unwrap_or(PlaylistScope::Personal).name(),
+                    playlist.tracks.len()
+                ))),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Queues every track in a playlist
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn load(
+    ctx: Context<'_>,
+    #[description = "Playlist name"] name: String,
+    #[description = "Personal or server, defaults to personal"] scope: Option<PlaylistScope>,
+    #[description = "Shuffle the tracks before adding them"]
+    #[flag]
+    shuffle: bool,
+) -> Result<(), Error> {
+    let Some(owner) = get_owner(&ctx, scope).await? else {
+        return Ok(());
+    };
+    let Some(playlist) = get_playlist(&ctx, owner, &name).await? else {
+        return Ok(());
+    };
+    if playlist.tracks.is_empty() {
+        ctx.say(format!("**{}** is empty!", playlist.name)).await?;
+        return Ok(());
+    }
+
+    let Some(guild_id) = utils::join_author_channel(&ctx).await? else {
+        return Ok(());
+    };
+
+    let name = playlist.name.clone();
+    let (added, refused) = enqueue_playlist(&ctx, guild_id, playlist, shuffle).await?;
+
+    ctx.say(match (added, refused) {
+        (0, Some(refused)) => refused,
+        (added, refused) => format!(
+            "Got it!. Added **{added}** tracks from **{name}** to the queue{}",
+            limits::footnote(refused)
+        ),
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes a playlist
+#[poise::command(slash_command)]
+pub async fn delete(
+    ctx: Context<'_>,
+    #[description = "Playlist name"] name: String,
+    #[description = "Personal or server, defaults to personal"] scope: Option<PlaylistScope>,
+) -> Result<(), Error> {
+    let Some(owner) = get_owner(&ctx, scope).await? else {
+        return Ok(());
+    };
+    let Some(playlist) = get_playlist(&ctx, owner, &name).await? else {
+        return Ok(());
+    };
+    if !can_edit(&ctx, owner, &playlist).await {
+        ctx.say("Only the person who made this playlist can delete it!")
+            .await?;
+        return Ok(());
+    }
+
+    playlists::modify(owner, |playlists| playlists.remove(&name)).await?;
+
+    ctx.say(format!("Deleted **{}**!", playlist.name)).await?;
+
+    Ok(())
+}
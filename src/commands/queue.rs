@@ -1,16 +1,24 @@
+use crate::effects;
+use crate::looping::LoopMode;
+use crate::settings;
 use crate::utils;
 use crate::utils::CustomMetadata;
 use crate::Context;
 use crate::Error;
 
 use std::fmt::Write;
+use std::time::Duration;
 
+use poise::serenity_prelude as serenity;
 use poise::serenity_prelude::colours::roles::DARK_GREEN;
 use poise::serenity_prelude::colours::roles::DARK_RED;
 
 use songbird::tracks::{LoopState, PlayMode};
 
-fn track_title(url: Option<String>, title: Option<String>) -> String {
+const PAGE_SIZE: usize = 10;
+const QUEUE_VIEW_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub fn track_title(url: Option<String>, title: Option<String>) -> String {
     let source_url = url.unwrap_or("https://www.youtube.com".to_string());
     let title = title.unwrap_or("Untitled".to_string());
     let mut title = regex::Regex::new(r"/\[.*\]/")
@@ -23,17 +31,20 @@ fn track_title(url: Option<String>, title: Option<String>) -> String {
         title = format!("{title}...");
     }
 
+    // library tracks have a file path instead, which discord can't link to
+    if !source_url.starts_with("http") {
+        return format!("**{title}**");
+    }
+
     format!("[{}]({})", title, source_url)
 }
 
 fn track_duration(
-    duration: Option<std::time::Duration>,
+    metadata: &CustomMetadata,
     info: songbird::tracks::TrackState,
+    loop_mode: LoopMode,
 ) -> String {
-    let position = info.position;
-    let Some(duration) = duration else {
-        return "".to_string();
-    };
+    let position = effects::source_position(metadata, info.position);
     let button = {
         match info.playing {
             PlayMode::Play => "▶️",
@@ -43,12 +54,22 @@ fn track_duration(
     };
 
     let loop_button = {
-        match info.loops {
-            LoopState::Infinite => "🔁",
-            LoopState::Finite(nonmax::NonMaxU32::ZERO) => "",
-            LoopState::Finite(_) => "🔁",
+        match (info.loops, loop_mode) {
+            (LoopState::Finite(nonmax::NonMaxU32::ZERO), LoopMode::Queue) => "🔁",
+            (LoopState::Finite(nonmax::NonMaxU32::ZERO), _) => "",
+            (_, LoopMode::Queue) => "🔂🔁",
+            _ => "🔂",
         }
     };
+    if metadata.live {
+        return format!(
+            "{button}{loop_button}🔴 **LIVE** `{}`",
+            utils::human_print_time(position)
+        );
+    }
+    let Some(duration) = metadata.aux_metadata.duration else {
+        return "".to_string();
+    };
     let time_slider = {
         const WIDTH: u8 = 15u8;
         let dot_pos = (WIDTH as f64 * (position.as_secs_f64() / duration.as_secs_f64())) as u8;
@@ -64,6 +85,18 @@ fn track_duration(
     format!("{button}{loop_button}{time_slider}{time}")
 }
 
+/// Shows what the radio is currently playing, if it told us
+fn stream_title(metadata: &CustomMetadata) -> String {
+    match metadata.stream_title.lock().unwrap().as_ref() {
+        Some(title) => format!("\n🎵 {title}"),
+        None => "".to_string(),
+    }
+}
+
+fn volume_fmt(volume: f32) -> String {
+    format!("🔊 {}%", (volume * 100.0).round())
+}
+
 fn queue_size_fmt(size: usize) -> String {
     match size {
         0usize => "-".to_string(),
@@ -72,6 +105,169 @@ fn queue_size_fmt(size: usize) -> String {
     }
 }
 
+#[derive(poise::Modal)]
+#[name = "Jump to page"]
+struct JumpModal {
+    #[name = "Page number"]
+    #[placeholder = "1"]
+    #[max_length = 6]
+    page: String,
+}
+
+fn page_count(queue_len: usize) -> usize {
+    queue_len.div_ceil(PAGE_SIZE).max(1)
+}
+
+fn queue_buttons(id: u64, page: usize, pages: usize) -> Vec<serenity::CreateActionRow> {
+    let button = |action: &str, emoji: char, disabled: bool| {
+        serenity::CreateButton::new(format!("{id}-{action}"))
+            .style(serenity::ButtonStyle::Secondary)
+            .emoji(emoji)
+            .disabled(disabled)
+    };
+    vec![serenity::CreateActionRow::Buttons(vec![
+        button("first", '⏮', page <= 1),
+        button("prev", '◀', page <= 1),
+        button("jump", '🔢', pages <= 1),
+        button("next", '▶', page >= pages),
+        button("last", '⏭', page >= pages),
+    ])]
+}
+
+async fn loop_mode(ctx: Context<'_>) -> LoopMode {
+    match ctx.guild_id() {
+        Some(guild_id) => {
+            settings::get_guild_settings(ctx.serenity_context(), guild_id)
+                .await
+                .loop_mode
+        }
+        None => LoopMode::Off,
+    }
+}
+
+/// Renders one page of the queue as it is right now, `page` is clamped to the pages there are
+///
+/// Returns `None` if nothing is queued anymore
+async fn queue_embed(
+    handler_lock: &tokio::sync::Mutex<songbird::Call>,
+    page: &mut usize,
+    loop_mode: LoopMode,
+) -> Result<Option<(serenity::CreateEmbed, usize)>, Error> {
+    let queue = handler_lock.lock().await.queue().current_queue();
+    let Some(trackhandle) = queue.first() else {
+        return Ok(None);
+    };
+
+    let pages = page_count(queue.len());
+    *page = (*page).clamp(1, pages);
+
+    // the current track is shown on its own, so it's left out of the first page
+    let start = ((*page - 1) * PAGE_SIZE).max(1);
+    let end = (*page * PAGE_SIZE).min(queue.len());
+    let queue_string = queue[start.min(end)..end].iter().enumerate().fold(
+        "".to_owned(),
+        |mut string, (index, handle)| {
+            let metadata = handle.data::<CustomMetadata>();
+            let songnum = start + index + 1;
+            let duration = if metadata.live {
+                "[LIVE]".to_string()
+            } else {
+                utils::human_print_time(metadata.aux_metadata.duration.unwrap_or_default())
+            };
+            let title = track_title(
+                metadata.aux_metadata.source_url.clone(),
+                metadata.aux_metadata.title.clone(),
+            );
+            _ = writeln!(
+                &mut string,
+                "`{songnum}.` {title} `{duration}` {}",
+                metadata.requested_by
+            );
+            string
+        },
+    );
+
+    let metadata = trackhandle.data::<CustomMetadata>();
+    let Ok(info) = trackhandle.get_info().await else {
+        // the track just ended, there's nothing sensible to show until the next one starts
+        return Ok(None);
+    };
+    let volume = volume_fmt(info.volume);
+    let embed = serenity::CreateEmbed::default()
+        .color(match info.playing {
+            PlayMode::Play => DARK_GREEN,
+            _ => DARK_RED,
+        })
+        .title(match info.playing {
+            PlayMode::Play => "Now playing",
+            PlayMode::Pause => "Paused",
+            _ => "Not playing anything now",
+        })
+        .description(format!(
+            "{}{}\nRequested by: {}\n\n{}\n\n**Up next:**\n{queue_string}",
+            track_title(
+                metadata.aux_metadata.source_url.clone(),
+                metadata.aux_metadata.title.clone()
+            ),
+            stream_title(&metadata),
+            metadata.requested_by,
+            track_duration(&metadata, info, loop_mode)
+        ))
+        .fields(vec![
+            ("In queue", queue_size_fmt(queue.len()), true),
+            ("Page", format!("{page} out of {pages}"), true),
+            ("Volume", volume, true),
+        ])
+        .footer(serenity::CreateEmbedFooter::new(format!(
+            "From: {}",
+            metadata
+                .aux_metadata
+                .channel
+                .clone()
+                .unwrap_or("Unknown".to_string())
+        )));
+
+    Ok(Some((embed, pages)))
+}
+
+/// Embed for a single track, used by /current and the now playing announcements
+pub fn now_playing_embed(
+    metadata: &CustomMetadata,
+    info: songbird::tracks::TrackState,
+    loop_mode: LoopMode,
+) -> serenity::CreateEmbed {
+    let volume = volume_fmt(info.volume);
+    serenity::CreateEmbed::default()
+        .color(match info.playing {
+            PlayMode::Play => DARK_GREEN,
+            _ => DARK_RED,
+        })
+        .title(match info.playing {
+            PlayMode::Play => "Now playing",
+            PlayMode::Pause => "Paused",
+            _ => "Not playing anything now",
+        })
+        .description(format!(
+            "{}{}\nRequested by: {}\n\n{}\n\n",
+            track_title(
+                metadata.aux_metadata.source_url.clone(),
+                metadata.aux_metadata.title.clone()
+            ),
+            stream_title(metadata),
+            metadata.requested_by,
+            track_duration(metadata, info, loop_mode)
+        ))
+        .field("Volume", volume, true)
+        .footer(serenity::CreateEmbedFooter::new(format!(
+            "From: {}",
+            metadata
+                .aux_metadata
+                .channel
+                .clone()
+                .unwrap_or("Unknown".to_string())
+        )))
+}
+
 /// Views currently queued tracks
 #[poise::command(slash_command)]
 pub async fn queue(
@@ -84,107 +280,94 @@ pub async fn queue(
         return Ok(());
     };
 
-    let guard = handler_lock.lock().await;
-
-    let queue = guard.queue();
-
-    let queue_len = queue.len();
-
-    if queue.is_empty() {
+    let mut page = page.unwrap_or(1usize);
+    let Some((mut embed, pages)) =
+        queue_embed(&handler_lock, &mut page, loop_mode(ctx).await).await?
+    else {
         ctx.say("There's nothing queued!").await?;
         return Ok(());
-    }
+    };
 
-    let page = page.unwrap_or(1usize).clamp(1, usize::MAX);
-
-    const PAGE_SIZE: usize = 10;
-
-    let queue_pages = queue
-        .current_queue()
-        .chunks(PAGE_SIZE)
-        .enumerate()
-        .map(|(chunk_index, slice)| {
-            slice
-                .iter()
-                .enumerate()
-                .filter(|(index, _)| !(*index == 0 && chunk_index == 0))
-                .fold("".to_owned(), |mut string, (index, handle)| {
-                    let (url, duration, title) = (
-                        handle
-                            .data::<CustomMetadata>()
-                            .aux_metadata
-                            .source_url
-                            .clone(),
-                        handle.data::<CustomMetadata>().aux_metadata.duration,
-                        handle.data::<CustomMetadata>().aux_metadata.title.clone(),
-                    );
-
-                    let songnum = index + 1 + chunk_index * PAGE_SIZE;
-                    let duration = duration.unwrap_or_else(Default::default);
-                    let duration = utils::human_print_time(duration);
-                    let title = track_title(url, title);
-                    _ = writeln!(&mut string, "`{songnum}.` {title} `{duration}`");
-                    string
-                })
-        })
-        .collect::<Vec<_>>();
+    let id = ctx.id();
+    let reply = ctx
+        .send(
+            poise::CreateReply::default()
+                .embed(embed.clone())
+                .components(queue_buttons(id, page, pages)),
+        )
+        .await?;
 
-    let queue_string = queue_pages.get(page - 1).unwrap();
+    let prefix = format!("{id}-");
+    loop {
+        let prefix = prefix.clone();
+        let Some(interaction) = serenity::ComponentInteractionCollector::new(ctx)
+            .channel_id(ctx.channel_id())
+            .filter(move |interaction| interaction.data.custom_id.starts_with(&prefix))
+            .timeout(QUEUE_VIEW_TIMEOUT)
+            .await
+        else {
+            break;
+        };
 
-    let Some(trackhandle) = queue.current() else {
-        ctx.say("I can't get the current track for some reason")
+        let action = interaction.data.custom_id.rsplit('-').next();
+        if action == Some("jump") {
+            let jump = poise::execute_modal_on_component_interaction::<JumpModal>(
+                ctx,
+                interaction,
+                None,
+                Some(QUEUE_VIEW_TIMEOUT),
+            )
             .await?;
-        return Ok(());
-    };
+            match jump.and_then(|jump| jump.page.trim().parse::<usize>().ok()) {
+                Some(jump) => page = jump,
+                None => continue,
+            }
+        } else {
+            page = match action {
+                Some("first") => 1,
+                Some("prev") => page.saturating_sub(1),
+                Some("next") => page + 1,
+                Some("last") => usize::MAX,
+                _ => page,
+            };
+            interaction
+                .create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+                .await?;
+        }
 
-    let (url, duration, title, requested_by, channel) = (
-        trackhandle
-            .data::<CustomMetadata>()
-            .aux_metadata
-            .source_url
-            .clone(),
-        trackhandle.data::<CustomMetadata>().aux_metadata.duration,
-        trackhandle
-            .data::<CustomMetadata>()
-            .aux_metadata
-            .title
-            .clone(),
-        trackhandle.data::<CustomMetadata>().requested_by.clone(),
-        trackhandle
-            .data::<CustomMetadata>()
-            .aux_metadata
-            .channel
-            .clone(),
-    );
-    let info = trackhandle.get_info().await?;
-    ctx.send(
-        poise::CreateReply::default().embed(
-            poise::serenity_prelude::CreateEmbed::default()
-                .color(match info.playing {
-                    PlayMode::Play => DARK_GREEN,
-                    _ => DARK_RED,
-                })
-                .title(match info.playing {
-                    PlayMode::Play => "Now playing",
-                    PlayMode::Pause => "Paused",
-                    _ => "Not playing anything now",
-                })
-                .description(format!(
-                    "{}\nRequested by: {requested_by}\n\n{}\n\n**Up next:**\n{queue_string}",
-                    track_title(url, title),
-                    track_duration(duration, info)
-                ))
-                .fields(vec![
-                    ("In queue", queue_size_fmt(queue_len), true),
-                    ("Page", format!("{page} out of {}", queue_pages.len()), true),
-                ])
-                .footer(poise::serenity_prelude::CreateEmbedFooter::new(format!(
-                    "From: {}",
-                    channel.unwrap_or("Unknown".to_string())
-                ))),
-        ),
-    )
-    .await?;
+        let Some((new_embed, pages)) =
+            queue_embed(&handler_lock, &mut page, loop_mode(ctx).await).await?
+        else {
+            reply
+                .edit(
+                    ctx,
+                    poise::CreateReply::default()
+                        .content("There's nothing queued!")
+                        .components(vec![]),
+                )
+                .await?;
+            return Ok(());
+        };
+        reply
+            .edit(
+                ctx,
+                poise::CreateReply::default()
+                    .embed(new_embed.clone())
+                    .components(queue_buttons(id, page, pages)),
+            )
+            .await?;
+        embed = new_embed;
+    }
+
+    // nobody touched it for a while, so the buttons stop working
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default()
+                .embed(embed)
+                .components(vec![]),
+        )
+        .await?;
 
     Ok(())
 }
@@ -201,52 +384,13 @@ pub async fn current(ctx: Context<'_>) -> Result<(), Error> {
         return Ok(());
     };
 
-    let (url, duration, title, requested_by, channel) = (
-        trackhandle
-            .data::<CustomMetadata>()
-            .aux_metadata
-            .source_url
-            .clone(),
-        trackhandle.data::<CustomMetadata>().aux_metadata.duration,
-        trackhandle
-            .data::<CustomMetadata>()
-            .aux_metadata
-            .title
-            .clone(),
-        trackhandle.data::<CustomMetadata>().requested_by.clone(),
-        trackhandle
-            .data::<CustomMetadata>()
-            .aux_metadata
-            .channel
-            .clone(),
-    );
-
     let info = trackhandle.get_info().await?;
 
-    ctx.send(
-        poise::CreateReply::default().embed(
-            poise::serenity_prelude::CreateEmbed::default()
-                .color(match info.playing {
-                    PlayMode::Play => DARK_GREEN,
-                    _ => DARK_RED,
-                })
-                .title(match info.playing {
-                    PlayMode::Play => "Now playing",
-                    PlayMode::Pause => "Paused",
-                    _ => "Not playing anything now",
-                })
-                .description(format!(
-                    "{}\nRequested by: {}\n\n{}\n\n",
-                    track_title(url, title),
-                    requested_by,
-                    track_duration(duration, info)
-                ))
-                .footer(poise::serenity_prelude::CreateEmbedFooter::new(format!(
-                    "From: {}",
-                    channel.unwrap_or("Unknown".to_string())
-                ))),
-        ),
-    )
+    ctx.send(poise::CreateReply::default().embed(now_playing_embed(
+        &trackhandle.data::<CustomMetadata>(),
+        info,
+        loop_mode(ctx).await,
+    )))
     .await?;
 
     Ok(())
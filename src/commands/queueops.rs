@@ -1,11 +1,39 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
 use std::time::Duration;
 
+use poise::serenity_prelude as serenity;
+use songbird::input::Input;
+use songbird::tracks::{LoopState, Queued, TrackHandle};
+
+use crate::announcer;
+use crate::autoplay;
+use crate::commands::playlist;
+use crate::effects;
+use crate::history;
+use crate::icy;
+use crate::idle;
+use crate::limits;
+use crate::looping::{self, LoopMode};
+use crate::permissions::{self, PermissionLevel};
+use crate::playlists::{self, PlaylistOwner};
+use crate::settings;
+use crate::suggestions;
 use crate::utils;
+use crate::utils::SourceKind;
+use crate::votes;
+use crate::ytdl;
 use crate::Context;
 use crate::Error;
 
+const AUTOCOMPLETE_CHOICES: usize = 25;
+// discord's limit for both the name and the value of a choice
+const AUTOCOMPLETE_LENGTH: usize = 100;
+const AUTOCOMPLETE_MIN_SEARCH: usize = 3;
+const VOTE_TIMEOUT: Duration = Duration::from_secs(120);
+
 /// Queues a track in, keep in mind that playlists and livestreams are not supported
-#[poise::command(slash_command)]
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn play_test(ctx: Context<'_>) -> Result<(), Error> {
     let Some(guild_id) = ctx.guild_id() else {
         ctx.say("I can only operate in a server!").await?;
@@ -43,83 +71,407 @@ pub async fn play_test(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-/// Queues a track in, keep in mind that playlists and livestreams are not supported
-#[poise::command(slash_command)]
+/// Adds tracks to the back of the queue, or to the front of it if `immediate` is set
+///
+/// With a fair queue, they're slotted in so each requester takes turns instead, unless
+/// `keep_order` is set for tracks that had their place already, like restored or looped ones. A
+/// track autoplay picked stops right away for anything people queue
+///
+/// The bot has to be in a voice channel in that guild already
+pub async fn enqueue(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    tracks: Vec<(Input, utils::CustomMetadata)>,
+    immediate: bool,
+    keep_order: bool,
+) -> Result<Vec<TrackHandle>, Error> {
+    let manager = songbird::get(ctx).await.unwrap().clone();
+    let Some(call) = manager.get(guild_id) else {
+        eyre::bail!("Not in a voice channel in {guild_id}");
+    };
+    let guild_settings = settings::get_guild_settings(ctx, guild_id).await;
+    let volume = guild_settings.volume() as f32 / 100.0;
+    // tracks that jump the line skip the turns too
+    let fair_queue = guild_settings.fair_queue() && !immediate && !keep_order;
+    let filters = guild_settings.filters;
+    let loops = match guild_settings.loop_mode {
+        LoopMode::Track => LoopState::Infinite,
+        _ => LoopState::Finite(nonmax::NonMaxU32::ZERO),
+    };
+
+    idle::cancel(ctx, guild_id).await;
+
+    let mut handler = call.lock().await;
+
+    // autoplay only fills in until someone asks for something
+    if let Some(trackhandle) = handler.queue().current() {
+        if autoplay_yields(
+            &trackhandle.data::<utils::CustomMetadata>(),
+            tracks.iter().map(|(_, metadata)| metadata),
+        ) {
+            discard(&trackhandle);
+            if let Some(queued) = handler.queue().dequeue(0) {
+                drop(queued.stop());
+            }
+        }
+    }
+
+    if immediate {
+        if let Some(trackhandle) = handler.queue().current() {
+            trackhandle.pause()?;
+            if !trackhandle.data::<utils::CustomMetadata>().live {
+                trackhandle.seek_async(Duration::from_secs(0)).await?;
+            }
+        };
+    }
+
+    let handles = tracks
+        .into_iter()
+        .map(|(source, mut metadata)| {
+            let source = effects::apply(source, &mut metadata, &filters);
+            // the duration is already known, so songbird doesn't have to ask yt-dlp for it again
+            let preload_time = metadata.aux_metadata.duration.map(|duration| {
+                effects::track_position(&metadata, duration).saturating_sub(Duration::from_secs(5))
+            });
+            let track = songbird::tracks::Track::new_with_data(source, Arc::new(metadata))
+                .volume(volume)
+                .loops(loops);
+            let trackhandle = handler.enqueue_with_preload(track, preload_time);
+            if fair_queue {
+                let requested_by = &trackhandle.data::<utils::CustomMetadata>().requested_by;
+                handler.queue().modify_queue(|queue| {
+                    // the track that's playing can't be moved
+                    if queue.len() <= 1 {
+                        return;
+                    }
+                    let Some(queued) = queue.pop_back() else {
+                        return;
+                    };
+                    let position = fair_position(queue, requested_by);
+                    queue.insert(position, queued);
+                });
+            }
+            if let Err(e) = announcer::add_events(ctx, guild_id, &trackhandle) {
+                log::error!("{:?}", e);
+            }
+            if let Err(e) = history::add_events(guild_id, &trackhandle) {
+                log::error!("{:?}", e);
+            }
+            if let Err(e) = looping::add_events(ctx, guild_id, &trackhandle) {
+                log::error!("{:?}", e);
+            }
+            if let Err(e) = autoplay::add_events(ctx, guild_id, &trackhandle) {
+                log::error!("{:?}", e);
+            }
+            if let Err(e) = idle::add_events(ctx, guild_id, &trackhandle) {
+                log::error!("{:?}", e);
+            }
+            if let Err(e) = icy::add_events(ctx, &trackhandle) {
+                log::error!("{:?}", e);
+            }
+            trackhandle
+        })
+        .collect::<Vec<_>>();
+
+    if immediate {
+        let added = handles.len();
+        handler.queue().modify_queue(|queue| {
+            let len = queue.len();
+            queue.rotate_right(added.min(len));
+        });
+        if let Some(trackhandle) = handler.queue().current() {
+            trackhandle.play()?;
+        }
+    }
+
+    Ok(handles)
+}
+
+/// Marks a track as taken out on purpose, so it doesn't loop back into the queue
+fn discard(trackhandle: &TrackHandle) {
+    trackhandle
+        .data::<utils::CustomMetadata>()
+        .discarded
+        .store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// Whether the playing track was picked by autoplay and has to make way for the ones being queued
+fn autoplay_yields<'a>(
+    current: &utils::CustomMetadata,
+    mut added: impl Iterator<Item = &'a utils::CustomMetadata>,
+) -> bool {
+    current.autoplay && added.any(|metadata| !metadata.autoplay)
+}
+
+/// Where a new track from `requested_by` goes in the queue so every requester gets a turn
+fn fair_position(queue: &VecDeque<Queued>, requested_by: &str) -> usize {
+    let requesters = queue
+        .iter()
+        .map(|trackhandle| trackhandle.data::<utils::CustomMetadata>())
+        .collect::<Vec<_>>();
+    let requesters = requesters
+        .iter()
+        .map(|metadata| metadata.requested_by.as_str())
+        .collect::<Vec<_>>();
+    // the playing track already had its turn
+    match requesters.split_first() {
+        Some((_, upcoming)) => 1 + fair_index(upcoming, requested_by),
+        None => 0,
+    }
+}
+
+/// Where a new track from `requested_by` goes among the requesters of the upcoming tracks
+///
+/// Each requester's tracks keep their order, the n-th one of everyone plays in the n-th round
+fn fair_index(upcoming: &[&str], requested_by: &str) -> usize {
+    let mut rounds = HashMap::<&str, usize>::new();
+    let upcoming_rounds = upcoming
+        .iter()
+        .map(|requester| {
+            let round = rounds.entry(requester).or_default();
+            *round += 1;
+            *round - 1
+        })
+        .collect::<Vec<_>>();
+    let round = rounds.get(requested_by).copied().unwrap_or(0);
+    upcoming_rounds
+        .into_iter()
+        .position(|other| other > round)
+        .unwrap_or(upcoming.len())
+}
+
+/// Makes a yt-dlp source for an entry whose metadata is already known, like a playlist or search entry
+///
+/// Returns `None` if the entry has no url
+pub fn ytdl_track(
+    http_client: &reqwest::Client,
+    entry: ytdl::Entry,
+    requested_by: String,
+) -> Option<(Input, utils::CustomMetadata)> {
+    let url = entry.aux_metadata.source_url.clone()?;
+    let source = songbird::input::YoutubeDl::new(http_client.clone(), url);
+    Some((
+        source.into(),
+        utils::CustomMetadata {
+            aux_metadata: entry.aux_metadata,
+            requested_by,
+            live: entry.live,
+            ..Default::default()
+        },
+    ))
+}
+
+/// Suggests search results, the server's recent tracks and saved playlists while typing a query
+async fn autocomplete_query(ctx: Context<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    let suggestions = suggestions::get_suggestions(ctx.serenity_context()).await;
+    let partial = partial.trim();
+    let lowercase = partial.to_lowercase();
+    let mut choices = Vec::new();
+
+    let mut owners = vec![PlaylistOwner::User(ctx.author().id)];
+    owners.extend(ctx.guild_id().map(PlaylistOwner::Guild));
+    for owner in owners {
+        let Ok(saved) = playlists::load(owner).await else {
+            continue;
+        };
+        choices.extend(
+            saved
+                .playlists
+                .values()
+                .filter(|playlist| playlist.name.to_lowercase().contains(&lowercase))
+                .map(|playlist| {
+                    (
+                        format!("📁 {} ({} tracks)", playlist.name, playlist.tracks.len()),
+                        format!("{}{}", playlists::QUERY_PREFIX, playlist.name),
+                    )
+                }),
+        );
+    }
+
+    if let Some(guild_id) = ctx.guild_id() {
+        choices.extend(
+            suggestions
+                .recent(guild_id, partial)
+                .into_iter()
+                .map(|track| (format!("🕘 {}", track.title), track.url)),
+        );
+    }
+
+    let is_url = partial.starts_with("http://") || partial.starts_with("https://");
+    if partial.chars().count() >= AUTOCOMPLETE_MIN_SEARCH
+        && !is_url
+        && suggestions.debounce(ctx.author().id).await
+    {
+        if let Some(results) = suggestions.search(partial).await {
+            choices.extend(results.into_iter().filter_map(|entry| {
+                let url = entry.aux_metadata.source_url?;
+                let duration = if entry.live {
+                    "[LIVE]".to_string()
+                } else {
+                    entry
+                        .aux_metadata
+                        .duration
+                        .map(utils::human_print_time)
+                        .unwrap_or_default()
+                };
+                Some((
+                    format!(
+                        "{} {duration}",
+                        entry.aux_metadata.title.as_deref().unwrap_or("Untitled")
+                    ),
+                    url,
+                ))
+            }));
+        }
+    }
+
+    choices
+        .into_iter()
+        // discord rejects the whole response if a single value is too long
+        .filter(|(_, value)| value.chars().count() <= AUTOCOMPLETE_LENGTH)
+        .take(AUTOCOMPLETE_CHOICES)
+        .map(|(name, value)| {
+            serenity::AutocompleteChoice::new(utils::truncate(&name, AUTOCOMPLETE_LENGTH), value)
+        })
+        .collect()
+}
+
+/// Queues a track, a playlist, a livestream or an internet radio in
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn play(
     ctx: Context<'_>,
-    #[description = "YouTube URL or query string"] query: String,
+    #[description = "YouTube URL, playlist URL, radio stream URL or query string"]
+    #[autocomplete = "autocomplete_query"]
+    query: String,
     #[description = "Play the track now (This will insert the track in the front of the queue and plays it!)"]
     #[flag]
     immediate: bool,
-    #[description = "Loop the track"]
+    #[description = "Loop the track (ignored for playlists)"]
     #[flag]
     track_loop: bool,
+    #[description = "Maximum number of tracks to add from a playlist"] limit: Option<usize>,
+    #[description = "Number of tracks to skip from the start of a playlist"] offset: Option<usize>,
+    #[description = "Shuffle the playlist's tracks before adding them"]
+    #[flag]
+    shuffle: bool,
 ) -> Result<(), Error> {
-    let Some(guild_id) = ctx.guild_id() else {
-        ctx.say("I can only operate in a server!").await?;
+    let Some(guild_id) = utils::join_author_channel(&ctx).await? else {
         return Ok(());
     };
-
-    let Some(channel_id) = guild_id
-        .to_guild_cached(ctx.serenity_context())
-        .unwrap()
-        .voice_states
-        .get(&ctx.author().id)
-        .and_then(|vstate| vstate.channel_id)
-    else {
-        ctx.say("You've gotta be in a voice channel to play!")
-            .await?;
-        return Ok(());
-    };
-    ctx.defer().await?;
     let http_client = {
         let data = ctx.serenity_context().data.read().await;
         data.get::<utils::HttpKey>().cloned().unwrap()
     };
 
-    let manager = songbird::get(ctx.serenity_context()).await.unwrap().clone();
-    let call = manager.join(guild_id, channel_id).await?;
-    let source = songbird::input::YoutubeDl::new(http_client, query);
+    let id = format!("<@{}>", ctx.author().id);
 
-    let mut source: songbird::input::Input = source.into();
+    if let Some(name) = query.strip_prefix(playlists::QUERY_PREFIX) {
+        if let Some(saved) = playlist::find_saved(&ctx, name.trim()).await? {
+            let name = saved.name.clone();
+            let (added, refused) =
+                playlist::enqueue_playlist(&ctx, guild_id, saved, shuffle).await?;
+            ctx.say(match (added, refused) {
+                (0, Some(refused)) => refused,
+                (added, refused) => format!(
+                    "Got it!. Added **{added}** tracks from **{name}** to the queue{}",
+                    limits::footnote(refused)
+                ),
+            })
+            .await?;
+            return Ok(());
+        }
+    }
 
-    let id = format!("<@{}>", ctx.author().id);
+    if ytdl::is_playlist_url(&query) {
+        let playlist = match ytdl::flat_playlist(&query, offset.unwrap_or(0), limit).await {
+            Ok(playlist) => playlist,
+            Err(e) => {
+                ctx.say(format!("Error fetching the playlist:\n```{:?}```", e))
+                    .await?;
+                return Ok(());
+            }
+        };
 
-    let aux_metadata = source.aux_metadata().await?;
-    let title = aux_metadata.title.clone();
+        let mut entries = playlist.entries;
+        if entries.is_empty() {
+            ctx.say("There's nothing in that playlist!").await?;
+            return Ok(());
+        }
+        if shuffle {
+            use rand::seq::SliceRandom;
+            entries.shuffle(&mut rand::rng());
+        }
+
+        let tracks = entries
+            .into_iter()
+            .filter_map(|entry| ytdl_track(&http_client, entry, id.clone()))
+            .collect::<Vec<_>>();
+        let (tracks, refused) = limits::apply(&ctx, guild_id, tracks).await?;
+        if let (true, Some(refused)) = (tracks.is_empty(), &refused) {
+            ctx.say(refused).await?;
+            return Ok(());
+        }
+
+        let handles = enqueue(ctx.serenity_context(), guild_id, tracks, immediate, false).await?;
+        suggestions::get_suggestions(ctx.serenity_context())
+            .await
+            .record_queued(guild_id, &handles);
+        suggestions::get_suggestions(ctx.serenity_context())
+            .await
+            .record_queued(guild_id, &handles);
+        let added = handles.len();
+
+        ctx.say(format!(
+            "Got it!. Added **{added}** tracks from **{}** to the queue{}",
+            playlist.title.as_deref().unwrap_or("Untitled"),
+            limits::footnote(refused)
+        ))
+        .await?;
 
-    let track = {
-        let mut handler = call.lock().await;
-        let track = songbird::tracks::Track::new_with_data(
-            source,
-            std::sync::Arc::new(utils::CustomMetadata {
+        return Ok(());
+    }
+
+    let (source, metadata) = if let Some(aux_metadata) = icy::probe(&http_client, &query).await {
+        let source = songbird::input::HttpRequest::new(http_client.clone(), query);
+        (
+            source.into(),
+            utils::CustomMetadata {
                 aux_metadata,
                 requested_by: id,
-            }),
-        );
-
-        if immediate {
-            if let Some(trackhandle) = handler.queue().current() {
-                trackhandle.pause()?;
-                trackhandle.seek_async(Duration::from_secs(0)).await?;
-            };
-            handler.enqueue(track).await;
-            handler.queue().modify_queue(|queue| {
-                if queue.len() == 1 {
-                    return;
-                }
-                if let Some(backelem) = queue.pop_back() {
-                    queue.push_front(backelem);
-                };
-            });
-            handler.queue().current().unwrap()
-        } else {
-            handler.enqueue(track).await
-        }
+                kind: SourceKind::Http,
+                live: true,
+                ..Default::default()
+            },
+        )
+    } else {
+        // songbird's own lookup leaves out whether it's live
+        let entry = ytdl::info(&query).await?;
+        let source = songbird::input::YoutubeDl::new(http_client.clone(), query);
+        (
+            source.into(),
+            utils::CustomMetadata {
+                aux_metadata: entry.aux_metadata,
+                requested_by: id,
+                live: entry.live,
+                ..Default::default()
+            },
+        )
     };
 
-    if track_loop {
-        track.enable_loop().unwrap();
+    let title = metadata.aux_metadata.title.clone();
+
+    let (tracks, refused) = limits::apply(&ctx, guild_id, vec![(source, metadata)]).await?;
+    if let Some(refused) = refused {
+        ctx.say(refused).await?;
+        return Ok(());
+    }
+
+    let handles = enqueue(ctx.serenity_context(), guild_id, tracks, immediate, false).await?;
+
+    if let Some(track) = handles.first() {
+        if track_loop {
+            track.enable_loop().unwrap();
+        }
     }
 
     ctx.say(format!(
@@ -132,7 +484,7 @@ pub async fn play(
 }
 
 /// Clears the queue, stop playing and leave the call
-#[poise::command(slash_command)]
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
     let Some(guild_id) = ctx.guild_id() else {
         ctx.say("I can only operate in a server!").await?;
@@ -153,12 +505,170 @@ pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-/// Skips to the next track in the queue
-#[poise::command(slash_command)]
+fn vote_message(title: &str, votes: usize, needed: usize) -> String {
+    format!("Voted to skip **{title}**, {votes}/{needed} votes")
+}
+
+fn vote_button(custom_id: &str) -> Vec<serenity::CreateActionRow> {
+    vec![serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(custom_id)
+            .style(serenity::ButtonStyle::Primary)
+            .emoji('⏭')
+            .label("Vote to skip"),
+    ])]
+}
+
+/// Casts the author's vote to skip the current track, the others can join in with a button
+async fn vote_skip(
+    ctx: Context<'_>,
+    guild_id: serenity::GuildId,
+    handler_lock: &tokio::sync::Mutex<songbird::Call>,
+    percent: u8,
+) -> Result<(), Error> {
+    let Some(trackhandle) = handler_lock.lock().await.queue().current() else {
+        ctx.say("Nothing is queued right now!").await?;
+        return Ok(());
+    };
+    let title = trackhandle
+        .data::<utils::CustomMetadata>()
+        .aux_metadata
+        .title
+        .clone()
+        .unwrap_or("Untitled".to_string());
+
+    let listeners = utils::listeners(ctx.serenity_context(), guild_id)
+        .await?
+        .unwrap_or_default();
+    if !listeners.contains(&ctx.author().id) {
+        ctx.say("You've gotta be listening to vote!").await?;
+        return Ok(());
+    }
+
+    let skip_votes = votes::get_skip_votes(ctx.serenity_context()).await;
+    let count = skip_votes.vote(guild_id, &trackhandle, ctx.author().id, &listeners);
+    let needed = votes::votes_needed(listeners.len(), percent);
+    if count >= needed {
+        handler_lock.lock().await.queue().skip()?;
+        ctx.say(format!("Vote passed, skipped **{title}**!"))
+            .await?;
+        return Ok(());
+    }
+
+    let custom_id = format!("voteskip-{}", ctx.id());
+    let reply = ctx
+        .send(
+            poise::CreateReply::default()
+                .content(vote_message(&title, count, needed))
+                .components(vote_button(&custom_id)),
+        )
+        .await?;
+
+    loop {
+        let filter_id = custom_id.clone();
+        let Some(interaction) = serenity::ComponentInteractionCollector::new(ctx)
+            .channel_id(ctx.channel_id())
+            .filter(move |interaction| interaction.data.custom_id == filter_id)
+            .timeout(VOTE_TIMEOUT)
+            .await
+        else {
+            break;
+        };
+
+        let current = handler_lock.lock().await.queue().current();
+        if current.map(|handle| handle.uuid()) != Some(trackhandle.uuid()) {
+            interaction
+                .create_response(
+                    ctx,
+                    serenity::CreateInteractionResponse::UpdateMessage(
+                        serenity::CreateInteractionResponseMessage::new()
+                            .content(format!(
+                                "**{title}** isn't playing anymore, the vote is over!"
+                            ))
+                            .components(vec![]),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+
+        let listeners = utils::listeners(ctx.serenity_context(), guild_id)
+            .await?
+            .unwrap_or_default();
+        if !listeners.contains(&interaction.user.id) {
+            interaction
+                .create_response(
+                    ctx,
+                    serenity::CreateInteractionResponse::Message(
+                        serenity::CreateInteractionResponseMessage::new()
+                            .content("You've gotta be listening to vote!")
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            continue;
+        }
+
+        let count = skip_votes.vote(guild_id, &trackhandle, interaction.user.id, &listeners);
+        let needed = votes::votes_needed(listeners.len(), percent);
+        let passed = count >= needed;
+        if passed {
+            handler_lock.lock().await.queue().skip()?;
+        }
+
+        interaction
+            .create_response(
+                ctx,
+                serenity::CreateInteractionResponse::UpdateMessage(if passed {
+                    serenity::CreateInteractionResponseMessage::new()
+                        .content(format!("Vote passed, skipped **{title}**!"))
+                        .components(vec![])
+                } else {
+                    serenity::CreateInteractionResponseMessage::new()
+                        .content(vote_message(&title, count, needed))
+                        .components(vote_button(&custom_id))
+                }),
+            )
+            .await?;
+        if passed {
+            return Ok(());
+        }
+    }
+
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default()
+                .content(format!("The vote to skip **{title}** ran out of time!"))
+                .components(vec![]),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Skips to the next track in the queue, or votes to skip it
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn next(ctx: Context<'_>) -> Result<(), Error> {
     let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
         return Ok(());
     };
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let guild_settings = settings::get_guild_settings(ctx.serenity_context(), guild_id).await;
+    if guild_settings.vote_skip()
+        && !permissions::is_dj(&ctx).await
+        && !permissions::is_requester(&ctx).await
+    {
+        return vote_skip(
+            ctx,
+            guild_id,
+            &handler_lock,
+            guild_settings.vote_skip_percent(),
+        )
+        .await;
+    }
 
     if let Err(e) = handler_lock.lock().await.queue().skip() {
         ctx.say(format!("Error running command:\n```{:?}```", e))
@@ -172,7 +682,7 @@ pub async fn next(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Shuffles all the next tracks in the queue
-#[poise::command(slash_command)]
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn shuffle(ctx: Context<'_>) -> Result<(), Error> {
     let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
         return Ok(());
@@ -201,8 +711,150 @@ pub async fn shuffle(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Turns a 1-based index from /queue into a position in the queue, the playing track can't be picked
+fn queue_index(index: usize, queue_len: usize) -> Result<usize, String> {
+    match index {
+        0 => Err("Tracks are numbered from 1, like in `/queue`!".to_string()),
+        1 => Err("That's the track that's playing right now!".to_string()),
+        index if index > queue_len => Err(format!(
+            "There's no track {index}, the queue only has {queue_len}!"
+        )),
+        index => Ok(index - 1),
+    }
+}
+
+/// Moves a track to another spot in the queue
+#[poise::command(slash_command, rename = "move", check = "permissions::check")]
+pub async fn move_track(
+    ctx: Context<'_>,
+    #[description = "Number of the track to move, as shown in /queue"] from: usize,
+    #[description = "Number it should have afterwards"] to: usize,
+) -> Result<(), Error> {
+    let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
+        return Ok(());
+    };
+
+    let guard = handler_lock.lock().await;
+    let queue = guard.queue();
+
+    let (from, to) = match (queue_index(from, queue.len()), queue_index(to, queue.len())) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(message), _) | (_, Err(message)) => {
+            ctx.say(message).await?;
+            return Ok(());
+        }
+    };
+
+    let moved = queue.modify_queue(|queue| {
+        let queued = queue.remove(from)?;
+        let trackhandle = queued.handle();
+        queue.insert(to, queued);
+        Some(trackhandle)
+    });
+
+    let title = moved
+        .and_then(|trackhandle| {
+            trackhandle
+                .data::<utils::CustomMetadata>()
+                .aux_metadata
+                .title
+                .clone()
+        })
+        .unwrap_or("Untitled".to_string());
+    ctx.say(format!("Moved **{title}** to {}!", to + 1)).await?;
+
+    Ok(())
+}
+
+/// Swaps two tracks in the queue
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn swap(
+    ctx: Context<'_>,
+    #[description = "Number of a track, as shown in /queue"] first: usize,
+    #[description = "Number of the track to swap it with"] second: usize,
+) -> Result<(), Error> {
+    let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
+        return Ok(());
+    };
+
+    let guard = handler_lock.lock().await;
+    let queue = guard.queue();
+
+    let (first, second) = match (
+        queue_index(first, queue.len()),
+        queue_index(second, queue.len()),
+    ) {
+        (Ok(first), Ok(second)) => (first, second),
+        (Err(message), _) | (_, Err(message)) => {
+            ctx.say(message).await?;
+            return Ok(());
+        }
+    };
+
+    queue.modify_queue(|queue| queue.swap(first, second));
+
+    ctx.say(format!("Swapped {} and {}!", first + 1, second + 1))
+        .await?;
+
+    Ok(())
+}
+
+/// Skips ahead to a track, dropping every track before it
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn skipto(
+    ctx: Context<'_>,
+    #[description = "Number of the track to play, as shown in /queue"] index: usize,
+) -> Result<(), Error> {
+    let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
+        return Ok(());
+    };
+
+    let guard = handler_lock.lock().await;
+    let queue = guard.queue();
+
+    let index = match queue_index(index, queue.len()) {
+        Ok(index) => index,
+        Err(message) => {
+            ctx.say(message).await?;
+            return Ok(());
+        }
+    };
+
+    let mut skipped = queue.modify_queue(|queue| queue.drain(..index).collect::<Vec<_>>());
+    // the playing track is skipped like with /next, the ones after it never got their turn
+    let current = skipped.remove(0);
+    for trackhandle in skipped {
+        discard(&trackhandle);
+        if let Err(e) = trackhandle.stop() {
+            log::error!("{:?}", e);
+        }
+    }
+    if let Err(e) = current.stop() {
+        log::error!("{:?}", e);
+    }
+
+    // songbird only moves on by itself if the track that ended was still in front
+    let Some(trackhandle) = queue.current() else {
+        return Ok(());
+    };
+    trackhandle.play()?;
+
+    ctx.say(format!(
+        "Skipped to **{}**!",
+        trackhandle
+            .data::<utils::CustomMetadata>()
+            .aux_metadata
+            .title
+            .clone()
+            .unwrap_or("Untitled".to_string())
+    ))
+    .await?;
+
+    Ok(())
+}
+
 /// Clears all the items in the queue, except for the current item
-#[poise::command(slash_command)]
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn clear(ctx: Context<'_>) -> Result<(), Error> {
     let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
         return Ok(());
@@ -226,6 +878,7 @@ pub async fn clear(ctx: Context<'_>) -> Result<(), Error> {
     queue.modify_queue(|queue| {
         let current = queue.pop_front().unwrap();
         while let Some(item) = queue.pop_back() {
+            discard(&item);
             if let Err(e) = item.stop() {
                 log::error!("{:?}", e);
             };
@@ -239,12 +892,16 @@ pub async fn clear(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// Removes an item at an index from the track queue
-#[poise::command(slash_command)]
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn remove(
     ctx: Context<'_>,
     #[description = "Index of the track to remove"] index: usize,
     #[description = "Number of additional tracks to remove, defaults to 0"] size: Option<usize>,
 ) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
     let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
         return Ok(());
     };
@@ -258,11 +915,30 @@ pub async fn remove(
         return Ok(());
     }
     let index = index.saturating_sub(1);
-    let size = size.unwrap_or(0);
+    let end = (index + size.unwrap_or(0) + 1).min(queue.len());
+    let targets = queue
+        .current_queue()
+        .get(index..end)
+        .map(|targets| targets.to_vec())
+        .unwrap_or_default();
+    if targets.is_empty() {
+        ctx.say("There's nothing at that index!").await?;
+        return Ok(());
+    }
+
+    // with the requester level, people can only take out what they queued themselves
+    let guild_settings = settings::get_guild_settings(ctx.serenity_context(), guild_id).await;
+    if permissions::required_level(&guild_settings, "remove") == PermissionLevel::Requester
+        && !permissions::can_remove(&ctx, &targets).await
+    {
+        ctx.say("You can only remove tracks you requested, unless you're a DJ!")
+            .await?;
+        return Ok(());
+    }
 
     queue.modify_queue(|queue| {
-        let max_size = (index + size).clamp(0, queue.len());
-        queue.drain(index..max_size).for_each(|track| {
+        queue.drain(index..end).for_each(|track| {
+            discard(&track);
             if let Err(e) = track.stop() {
                 log::error!("{:?}", e);
             };
@@ -273,3 +949,85 @@ pub async fn remove(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{autoplay_yields, fair_index, queue_index};
+    use crate::utils::CustomMetadata;
+
+    #[test]
+    fn converts_to_queue_positions() {
+        assert_eq!(queue_index(2, 5), Ok(1));
+        assert_eq!(queue_index(5, 5), Ok(4));
+    }
+
+    #[test]
+    fn rejects_zero() {
+        assert!(queue_index(0, 5).is_err());
+    }
+
+    #[test]
+    fn rejects_the_playing_track() {
+        assert!(queue_index(1, 5).is_err());
+    }
+
+    #[test]
+    fn rejects_past_the_end() {
+        assert!(queue_index(6, 5).is_err());
+        assert!(queue_index(2, 0).is_err());
+    }
+
+    fn metadata(autoplay: bool) -> CustomMetadata {
+        CustomMetadata {
+            autoplay,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn autoplay_yields_to_requests() {
+        let added = [metadata(false)];
+        assert!(autoplay_yields(&metadata(true), added.iter()));
+    }
+
+    #[test]
+    fn autoplay_yields_if_any_track_is_requested() {
+        let added = [metadata(true), metadata(false)];
+        assert!(autoplay_yields(&metadata(true), added.iter()));
+    }
+
+    #[test]
+    fn autoplay_keeps_playing_for_autoplay() {
+        let added = [metadata(true)];
+        assert!(!autoplay_yields(&metadata(true), added.iter()));
+    }
+
+    #[test]
+    fn requested_tracks_keep_playing() {
+        let added = [metadata(false)];
+        assert!(!autoplay_yields(&metadata(false), added.iter()));
+    }
+
+    #[test]
+    fn first_track_goes_first() {
+        assert_eq!(fair_index(&[], "a"), 0);
+    }
+
+    #[test]
+    fn new_requesters_go_after_the_first_round() {
+        assert_eq!(fair_index(&["a", "a", "a"], "b"), 1);
+        assert_eq!(fair_index(&["a", "b", "a", "b"], "c"), 2);
+    }
+
+    #[test]
+    fn requesters_take_turns() {
+        assert_eq!(fair_index(&["a", "b", "a"], "b"), 3);
+        assert_eq!(fair_index(&["a", "b", "a", "a"], "b"), 3);
+    }
+
+    #[test]
+    fn a_requesters_tracks_keep_their_order() {
+        assert_eq!(fair_index(&["a", "b", "a", "b"], "a"), 4);
+        assert_eq!(fair_index(&["a", "a", "a"], "a"), 3);
+    }
+}
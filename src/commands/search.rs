@@ -0,0 +1,201 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+
+use crate::commands::queueops;
+use crate::limits;
+use crate::permissions;
+use crate::suggestions;
+use crate::utils;
+use crate::ytdl;
+use crate::Context;
+use crate::Error;
+
+const DEFAULT_RESULTS: usize = 10;
+const PICK_TIMEOUT: Duration = Duration::from_secs(60);
+// discord's limit for select menu labels and descriptions
+const OPTION_LENGTH: usize = 100;
+
+fn result_details(entry: &ytdl::Entry) -> String {
+    let duration = if entry.live {
+        "[LIVE]".to_string()
+    } else {
+        entry
+            .aux_metadata
+            .duration
+            .map(utils::human_print_time)
+            .unwrap_or_default()
+    };
+    format!(
+        "{} {duration}",
+        entry
+            .aux_metadata
+            .channel
+            .as_deref()
+            .unwrap_or("Unknown channel"),
+    )
+}
+
+/// Searches YouTube and lets you pick which results to queue
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "What to look for"] query: String,
+    #[description = "Number of results to show, defaults to 10"]
+    #[min = 1]
+    #[max = 25]
+    results: Option<usize>,
+) -> Result<(), Error> {
+    // joining waits until something is picked, so a search that goes nowhere doesn't leave the bot in voice
+    let Some((guild_id, _)) = utils::author_channel(&ctx).await? else {
+        return Ok(());
+    };
+    ctx.defer().await?;
+
+    let count = results.unwrap_or(DEFAULT_RESULTS).clamp(1, 25);
+    let entries = match ytdl::search(&query, count).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            ctx.say(format!("Error searching:\n```{:?}```", e)).await?;
+            return Ok(());
+        }
+    };
+    if entries.is_empty() {
+        ctx.say("Couldn't find anything like that!").await?;
+        return Ok(());
+    }
+
+    let description =
+        entries
+            .iter()
+            .enumerate()
+            .fold(String::new(), |mut string, (index, entry)| {
+                _ = writeln!(
+                    &mut string,
+                    "`{}.` **{}** - {}",
+                    index + 1,
+                    entry.aux_metadata.title.as_deref().unwrap_or("Untitled"),
+                    result_details(entry)
+                );
+                string
+            });
+
+    let custom_id = format!("search-{}", ctx.id());
+    let options = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            serenity::CreateSelectMenuOption::new(
+                utils::truncate(
+                    &format!(
+                        "{}. {}",
+                        index + 1,
+                        entry.aux_metadata.title.as_deref().unwrap_or("Untitled")
+                    ),
+                    OPTION_LENGTH,
+                ),
+                index.to_string(),
+            )
+            .description(utils::truncate(&result_details(entry), OPTION_LENGTH))
+        })
+        .collect();
+    let menu = serenity::CreateSelectMenu::new(
+        custom_id.clone(),
+        serenity::CreateSelectMenuKind::String { options },
+    )
+    .placeholder("Pick the tracks to queue")
+    .min_values(1)
+    .max_values(entries.len() as u8);
+
+    let embed = serenity::CreateEmbed::default()
+        .title(format!("Results for \"{query}\""))
+        .description(description);
+    let reply = ctx
+        .send(
+            poise::CreateReply::default()
+                .embed(embed.clone())
+                .components(vec![serenity::CreateActionRow::SelectMenu(menu)]),
+        )
+        .await?;
+
+    let interaction = serenity::ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .channel_id(ctx.channel_id())
+        .filter(move |interaction| interaction.data.custom_id == custom_id)
+        .timeout(PICK_TIMEOUT)
+        .await;
+
+    // the menu only works once, so it goes away either way
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default()
+                .embed(embed)
+                .components(vec![]),
+        )
+        .await?;
+
+    let Some(interaction) = interaction else {
+        return Ok(());
+    };
+    interaction
+        .create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+        .await?;
+
+    let serenity::ComponentInteractionDataKind::StringSelect { values } = &interaction.data.kind
+    else {
+        return Ok(());
+    };
+
+    let http_client = {
+        let data = ctx.serenity_context().data.read().await;
+        data.get::<utils::HttpKey>().cloned().unwrap()
+    };
+    let id = format!("<@{}>", ctx.author().id);
+    let tracks = values
+        .iter()
+        .filter_map(|value| value.parse::<usize>().ok())
+        .filter_map(|index| entries.get(index).cloned())
+        .filter_map(|entry| queueops::ytdl_track(&http_client, entry, id.clone()))
+        .collect::<Vec<_>>();
+    let (tracks, refused) = limits::apply(&ctx, guild_id, tracks).await?;
+    if let (true, Some(refused)) = (tracks.is_empty(), &refused) {
+        ctx.say(refused).await?;
+        return Ok(());
+    }
+
+    let names = tracks
+        .iter()
+        .map(|(_, metadata)| {
+            metadata
+                .aux_metadata
+                .title
+                .clone()
+                .unwrap_or("Untitled".to_string())
+        })
+        .collect::<Vec<_>>();
+
+    if utils::join_author_channel(&ctx).await?.is_none() {
+        return Ok(());
+    }
+    let handles = queueops::enqueue(ctx.serenity_context(), guild_id, tracks, false, false).await?;
+    suggestions::get_suggestions(ctx.serenity_context())
+        .await
+        .record_queued(guild_id, &handles);
+
+    ctx.say(match names.as_slice() {
+        [name] => format!(
+            "Got it!. Added **{name}** to the queue{}",
+            limits::footnote(refused)
+        ),
+        names => format!(
+            "Got it!. Added **{}** tracks to the queue{}",
+            names.len(),
+            limits::footnote(refused)
+        ),
+    })
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use std::fmt::Write;
+
+use poise::ChoiceParameter;
+
+use crate::settings;
+use crate::settings::Setting;
+use crate::utils;
+use crate::Context;
+use crate::Error;
+
+/// Only lets members with the Manage Server permission, or bot owners through (also runs for subcommands)
+async fn can_manage_settings(ctx: Context<'_>) -> Result<bool, Error> {
+    let can_manage = utils::can_manage_guild(&ctx).await;
+
+    if !can_manage {
+        ctx.say("You need the Manage Server permission to change my settings!")
+            .await?;
+    }
+
+    Ok(can_manage)
+}
+
+/// Views or changes the bot's settings for this server
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("view", "set", "reset"),
+    subcommand_required,
+    check = "can_manage_settings"
+)]
+pub async fn settings(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Views the settings for this server
+#[poise::command(slash_command)]
+pub async fn view(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+
+    let guild_settings = settings::get_guild_settings(ctx.serenity_context(), guild_id).await;
+
+    let description = Setting::all()
+        .iter()
+        .fold(String::new(), |mut string, setting| {
+            _ = writeln!(
+                &mut string,
+                "**{}**: `{}`{}\n{}",
+                setting.name(),
+                setting.value(&guild_settings),
+                if setting.is_default(&guild_settings) {
+                    " (default)"
+                } else {
+                    ""
+                },
+                setting.describe()
+            );
+            string
+        });
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            poise::serenity_prelude::CreateEmbed::default()
+                .title("Server settings")
+                .description(description),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Changes a setting for this server
+#[poise::command(slash_command)]
+pub async fn set(
+    ctx: Context<'_>,
+    #[description = "Setting to change"] setting: Setting,
+    #[description = "New value"] value: String,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+
+    let store = settings::get_settings_store(ctx.serenity_context()).await;
+
+    if let Err(message) = store
+        .try_modify(guild_id, |guild_settings| {
+            setting.set(guild_settings, &value)
+        })
+        .await?
+    {
+        ctx.say(message).await?;
+        return Ok(());
+    }
+
+    ctx.say(format!("Set **{}** to `{value}`!", setting.name()))
+        .await?;
+
+    Ok(())
+}
+
+/// Resets a setting, or every setting, back to the default
+#[poise::command(slash_command)]
+pub async fn reset(
+    ctx: Context<'_>,
+    #[description = "Setting to reset, resets everything if unset"] setting: Option<Setting>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+
+    let store = settings::get_settings_store(ctx.serenity_context()).await;
+
+    store
+        .modify(guild_id, |guild_settings| match setting {
+            Some(setting) => setting.reset(guild_settings),
+            // filters and permission levels have their own commands
+            None => Setting::all()
+                .iter()
+                .for_each(|setting| setting.reset(guild_settings)),
+        })
+        .await?;
+
+    match setting {
+        Some(setting) => ctx.say(format!("Reset **{}**!", setting.name())).await?,
+        None => ctx.say("Reset all settings!").await?,
+    };
+
+    Ok(())
+}
@@ -1,10 +1,50 @@
+use crate::effects;
+use crate::idle;
+use crate::looping::LoopMode;
+use crate::permissions;
+use crate::persistence;
+use crate::settings;
 use crate::utils;
 use crate::Context;
 use crate::Error;
 
-/// Unpauses the current track
-#[poise::command(slash_command)]
+/// Joins the author and queues the kept queue back up, returns how many tracks it had
+///
+/// Returns `None` if the author isn't somewhere it can be played
+async fn restore_kept_queue(
+    ctx: &Context<'_>,
+    kept: &persistence::KeptQueue,
+) -> Result<Option<usize>, Error> {
+    let Some(guild_id) = utils::join_author_channel(ctx).await? else {
+        return Ok(None);
+    };
+    let restored = persistence::restore_kept_queue(ctx.serenity_context(), guild_id, kept).await?;
+    Ok(Some(restored))
+}
+
+/// Unpauses the current track, or brings back the queue saved when everyone left
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn resume(ctx: Context<'_>) -> Result<(), Error> {
+    if let Some(guild_id) = ctx.guild_id() {
+        let manager = songbird::get(ctx.serenity_context()).await.unwrap().clone();
+        if manager.get(guild_id).is_none() {
+            if let Some(kept) = persistence::take_kept_queue(guild_id).await? {
+                let restored = restore_kept_queue(&ctx, &kept).await;
+                // it's only gone once it's back in the queue
+                if !matches!(restored, Ok(Some(_))) {
+                    persistence::put_back_kept_queue(guild_id, &kept).await?;
+                }
+                if let Some(restored) = restored? {
+                    ctx.say(format!(
+                        "Got it!. Picked the queue back up with {restored} tracks"
+                    ))
+                    .await?;
+                }
+                return Ok(());
+            }
+        }
+    }
+
     let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
         return Ok(());
     };
@@ -22,13 +62,17 @@ pub async fn resume(ctx: Context<'_>) -> Result<(), Error> {
         return Ok(());
     }
 
+    if let Some(guild_id) = ctx.guild_id() {
+        idle::cancel(ctx.serenity_context(), guild_id).await;
+    }
+
     ctx.say("Resumed!").await?;
 
     Ok(())
 }
 
 /// Pauses the current track
-#[poise::command(slash_command)]
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn pause(ctx: Context<'_>) -> Result<(), Error> {
     let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
         return Ok(());
@@ -45,13 +89,17 @@ pub async fn pause(ctx: Context<'_>) -> Result<(), Error> {
         return Ok(());
     };
 
+    if let Some(guild_id) = ctx.guild_id() {
+        idle::start(ctx.serenity_context(), guild_id).await;
+    }
+
     ctx.say("Paused!").await?;
 
     Ok(())
 }
 
 /// Restarts the current track
-#[poise::command(slash_command)]
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn replay(ctx: Context<'_>) -> Result<(), Error> {
     let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
         return Ok(());
@@ -62,6 +110,11 @@ pub async fn replay(ctx: Context<'_>) -> Result<(), Error> {
         return Ok(());
     };
 
+    if trackhandle.data::<crate::utils::CustomMetadata>().live {
+        ctx.say("Can't restart a livestream!").await?;
+        return Ok(());
+    }
+
     if let Err(e) = trackhandle
         .seek_async(std::time::Duration::from_secs(0))
         .await
@@ -76,8 +129,8 @@ pub async fn replay(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-/// Restarts the current track
-#[poise::command(slash_command)]
+/// Seeks to a time in the current track
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn seek(
     ctx: Context<'_>,
     #[description = "Time to seek to, E.g '1min', '2min 2s'"] time: String,
@@ -99,13 +152,14 @@ pub async fn seek(
         }
     };
 
-    if let Some(total) = trackhandle
-        .data::<tokio::sync::RwLock<crate::utils::CustomMetadata>>()
-        .read()
-        .await
-        .aux_metadata
-        .duration
-    {
+    let metadata = trackhandle.data::<crate::utils::CustomMetadata>();
+
+    if metadata.live {
+        ctx.say("Can't seek in a livestream!").await?;
+        return Ok(());
+    }
+
+    if let Some(total) = metadata.aux_metadata.duration {
         if dur > total {
             ctx.say("The track is not that long!").await?;
             return Ok(());
@@ -115,7 +169,10 @@ pub async fn seek(
         return Ok(());
     }
 
-    if let Err(e) = trackhandle.seek_async(dur).await {
+    if let Err(e) = trackhandle
+        .seek_async(effects::track_position(&metadata, dur))
+        .await
+    {
         ctx.say(format!("Error running track command:\n```{:?}```", e))
             .await?;
         return Ok(());
@@ -127,8 +184,44 @@ pub async fn seek(
     Ok(())
 }
 
+/// Loops the whole queue or every track, or stops looping
+#[poise::command(slash_command, rename = "loop", check = "permissions::check")]
+pub async fn loop_mode(
+    ctx: Context<'_>,
+    #[description = "What to loop"] mode: LoopMode,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+
+    settings::get_settings_store(ctx.serenity_context())
+        .await
+        .modify(guild_id, |guild_settings| guild_settings.loop_mode = mode)
+        .await?;
+
+    // tracks loop by themselves in track mode, the queue is looped as tracks end
+    let manager = songbird::get(ctx.serenity_context()).await.unwrap().clone();
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let queue = handler_lock.lock().await.queue().current_queue();
+        for trackhandle in queue {
+            let result = match mode {
+                LoopMode::Track => trackhandle.enable_loop(),
+                LoopMode::Queue | LoopMode::Off => trackhandle.disable_loop(),
+            };
+            if let Err(e) = result {
+                log::error!("{:?}", e);
+            }
+        }
+    }
+
+    ctx.say(format!("{}!", mode.describe())).await?;
+
+    Ok(())
+}
+
 /// Sets the current track to loop
-#[poise::command(slash_command)]
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn loop_current(
     ctx: Context<'_>,
     #[description = "Number of times to loop the current track, set to zero or unset for an infinite loop"]
@@ -161,7 +254,7 @@ pub async fn loop_current(
 }
 
 /// Stops looping the current track
-#[poise::command(slash_command)]
+#[poise::command(slash_command, check = "permissions::check")]
 pub async fn stop_looping(ctx: Context<'_>) -> Result<(), Error> {
     let Some(handler_lock) = utils::get_handler_lock(&ctx).await? else {
         return Ok(());
@@ -182,3 +275,40 @@ pub async fn stop_looping(ctx: Context<'_>) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Changes the volume of the current track, and of every track played after it
+#[poise::command(slash_command, check = "permissions::check")]
+pub async fn volume(
+    ctx: Context<'_>,
+    #[description = "Volume in percent, from 0 to 200"]
+    #[min = 0]
+    #[max = 200]
+    volume: u16,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(());
+    };
+    let volume = volume.min(settings::MAX_VOLUME);
+
+    settings::get_settings_store(ctx.serenity_context())
+        .await
+        .modify(guild_id, |guild_settings| {
+            guild_settings.volume = Some(volume)
+        })
+        .await?;
+
+    let manager = songbird::get(ctx.serenity_context()).await.unwrap().clone();
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let queue = handler_lock.lock().await.queue().current_queue();
+        for trackhandle in queue {
+            if let Err(e) = trackhandle.set_volume(volume as f32 / 100.0) {
+                log::error!("{:?}", e);
+            }
+        }
+    }
+
+    ctx.say(format!("Volume set to {volume}%!")).await?;
+
+    Ok(())
+}
@@ -1,26 +1,185 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+
 use tokio::io::AsyncReadExt;
 
-static CONFIG: tokio::sync::OnceCell<Config> = tokio::sync::OnceCell::const_new();
+static CONFIG: RwLock<Option<Arc<Config>>> = RwLock::new(None);
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Environment variables starting with this override the config field named by the rest, like
+/// `MUSICALCAT_DISCORD_TOKEN`
+const ENV_PREFIX: &str = "MUSICALCAT_";
 
-pub async fn init_config(path: &std::path::Path) -> eyre::Result<&'static Config> {
+/// Reads and checks a config file without touching the one in use
+///
+/// A missing file is fine as long as the environment has everything that's required
+pub async fn read_config(path: &Path) -> eyre::Result<Config> {
     let mut cfg_str = String::new();
-    tokio::fs::File::open(path)
-        .await?
-        .read_to_string(&mut cfg_str)
-        .await?;
-    let config = toml::from_str(&cfg_str)?;
-    CONFIG.set(config)?;
-    Ok(CONFIG.get().unwrap())
+    match tokio::fs::File::open(path).await {
+        Ok(mut file) => {
+            file.read_to_string(&mut cfg_str).await?;
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            log::warn!(
+                "There's no config file at {}, only using the environment",
+                path.display()
+            );
+        }
+        Err(e) => return Err(e.into()),
+    }
+    let mut table = toml::from_str::<toml::Table>(&cfg_str)?;
+    apply_env_overrides(&mut table);
+
+    let mut config: Config = toml::Value::Table(table).try_into()?;
+    if let Some(token_file) = &config.discord_token_file {
+        let token = tokio::fs::read_to_string(token_file).await.map_err(|e| {
+            eyre::eyre!(
+                "Can't read discord_token_file {}: {e}",
+                token_file.display()
+            )
+        })?;
+        config.discord_token = token.trim().to_string();
+    }
+    config.validate()?;
+    Ok(config)
+}
+
+/// Replaces the fields that have an environment variable set
+fn apply_env_overrides(table: &mut toml::Table) {
+    let vars = std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)));
+    apply_overrides(table, vars);
+}
+
+fn apply_overrides(table: &mut toml::Table, vars: impl IntoIterator<Item = (String, String)>) {
+    for (name, value) in vars {
+        let Some(field) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let field = field.to_lowercase();
+        if !config_fields().contains(&field.as_str()) {
+            log::warn!("{name} doesn't match any config field, so it's ignored");
+            continue;
+        }
+        let value = env_value(&field, value);
+        log::info!("Setting {field} from {name}");
+        table.insert(field, value);
+    }
+}
+
+/// Takes the value as it is for text fields, and reads it like config.toml does for the rest
+///
+/// Anything that isn't valid toml is left as text, so the field's error names it
+fn env_value(field: &str, value: String) -> toml::Value {
+    let parsed = toml::from_str::<toml::Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut parsed| parsed.remove("value"));
+    let text = toml::Value::String(value);
+    match parsed {
+        Some(parsed) if !fits(field, text.clone()) => parsed,
+        _ => text,
+    }
+}
+
+/// Whether `field` can be set to `value`, every other field has a default
+fn fits(field: &str, value: toml::Value) -> bool {
+    let table = toml::Table::from_iter([(field.to_string(), value)]);
+    toml::Value::Table(table).try_into::<Config>().is_ok()
+}
+
+/// The names of the config fields, as serde reads them
+fn config_fields() -> &'static [&'static str] {
+    static FIELDS: OnceLock<&'static [&'static str]> = OnceLock::new();
+    FIELDS.get_or_init(|| {
+        let mut fields: &'static [&'static str] = &[];
+        // it bails as soon as it has the names
+        _ = <Config as serde::Deserialize>::deserialize(FieldNames(&mut fields));
+        fields
+    })
+}
+
+/// Deserializer that only takes note of the field names a struct asks for
+struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> serde::Deserializer<'de> for FieldNames<'_> {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(serde::de::Error::custom("only structs have field names"))
+    }
+
+    fn deserialize_struct<V: serde::de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(serde::de::Error::custom("only the field names were needed"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+pub async fn init_config(path: &Path) -> eyre::Result<Arc<Config>> {
+    let config = Arc::new(read_config(path).await?);
+    config.warn_presence_problems();
+    CONFIG_PATH
+        .set(path.to_path_buf())
+        .map_err(|_| eyre::eyre!("configs is already initialized"))?;
+    *CONFIG.write().unwrap() = Some(config.clone());
+    Ok(config)
+}
+
+/// Reads the config file again and swaps it in if it's valid, the old one stays otherwise
+pub async fn reload_config() -> eyre::Result<Arc<Config>> {
+    let path = config_path();
+    let mut config = read_config(path).await?;
+    let problems = config.presence_problems();
+    if !problems.is_empty() {
+        eyre::bail!("{}", problems.join(", "));
+    }
+
+    let old = get_config();
+    let needs_restart = old.needs_restart(&config);
+    if !needs_restart.is_empty() {
+        log::warn!(
+            "Changes to {} only apply after a restart",
+            needs_restart.join(", ")
+        );
+    }
+    config.keep_startup_fields(&old);
+
+    let config = Arc::new(config);
+    *CONFIG.write().unwrap() = Some(config.clone());
+    Ok(config)
+}
+
+pub fn config_path() -> &'static Path {
+    CONFIG_PATH.get().expect("configs isn't initialized")
 }
 
-pub fn get_config() -> &'static Config {
-    CONFIG.get().expect("configs isn't initialized")
+pub fn get_config() -> Arc<Config> {
+    CONFIG
+        .read()
+        .unwrap()
+        .clone()
+        .expect("configs isn't initialized")
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct Config {
+    #[serde(default)]
     pub discord_token: String,
+    /// Read into `discord_token` instead, so the token can live in a secrets file
+    #[serde(default)]
+    pub discord_token_file: Option<PathBuf>,
 
+    #[serde(default)]
     pub owners: std::collections::HashSet<u64>,
     #[serde(default = "default_bot_status")]
     pub bot_status: String,
@@ -32,6 +191,123 @@ pub struct Config {
     pub bot_activity_url: String,
     #[serde(default = "default_bot_leave")]
     pub bot_leave_on_empty: bool,
+    #[serde(default = "default_empty_grace_seconds")]
+    pub empty_grace_seconds: u32,
+    #[serde(default)]
+    pub keep_queue_on_leave: bool,
+    #[serde(default = "default_announce_tracks")]
+    pub announce_tracks: bool,
+    #[serde(default)]
+    pub vote_skip: bool,
+    #[serde(default = "default_vote_skip_percent")]
+    pub vote_skip_percent: u8,
+    #[serde(default)]
+    pub fair_queue: bool,
+    #[serde(default)]
+    pub autoplay: bool,
+    #[serde(default)]
+    pub idle_timeout_minutes: u32,
+    #[serde(default)]
+    pub max_track_minutes: u32,
+    #[serde(default)]
+    pub max_user_tracks: usize,
+    #[serde(default)]
+    pub max_queue_length: usize,
+    #[serde(default = "default_allow_duplicates")]
+    pub allow_duplicates: bool,
+    #[serde(default = "default_volume")]
+    pub default_volume: u16,
+    #[serde(default = "default_data_dir")]
+    pub data_dir: std::path::PathBuf,
+    #[serde(default)]
+    pub library_dir: Option<std::path::PathBuf>,
+    #[serde(default)]
+    pub lyrics_url: String,
+}
+
+const BOT_STATUSES: &[&str] = &["online", "idle", "donotdisturb", "offline", "invisible"];
+const BOT_ACTIVITY_TYPES: &[&str] = &["listening", "streaming", "playing", "watching", "competing"];
+
+impl Config {
+    /// Catches values that would otherwise only blow up once they're used
+    fn validate(&self) -> eyre::Result<()> {
+        if self.discord_token.trim().is_empty() {
+            eyre::bail!("discord_token can't be empty, set it or discord_token_file");
+        }
+        if !(1..=100).contains(&self.vote_skip_percent) {
+            eyre::bail!("vote_skip_percent has to be from 1 to 100");
+        }
+        if self.default_volume > crate::settings::MAX_VOLUME {
+            eyre::bail!(
+                "default_volume has to be from 0 to {}",
+                crate::settings::MAX_VOLUME
+            );
+        }
+        Ok(())
+    }
+
+    /// Presence settings the bot can't use, it falls back to being online and listening to music
+    ///
+    /// These only warn at startup like they always did, but a reload with them is refused
+    pub fn presence_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !BOT_STATUSES.contains(&self.bot_status.to_lowercase().as_str()) {
+            problems.push(format!(
+                "bot_status `{}` isn't one of {}",
+                self.bot_status,
+                BOT_STATUSES.join(", ")
+            ));
+        }
+        let activity_type = self.bot_activity_type.to_lowercase();
+        if !BOT_ACTIVITY_TYPES.contains(&activity_type.as_str()) {
+            problems.push(format!(
+                "bot_activity_type `{}` isn't one of {}",
+                self.bot_activity_type,
+                BOT_ACTIVITY_TYPES.join(", ")
+            ));
+        }
+        if activity_type == "streaming" && reqwest::Url::parse(&self.bot_activity_url).is_err() {
+            problems.push(format!(
+                "bot_activity_url `{}` has to be a valid url to stream",
+                self.bot_activity_url
+            ));
+        }
+        problems
+    }
+
+    /// Logs the presence problems, for when the config is used anyway
+    pub fn warn_presence_problems(&self) {
+        for problem in self.presence_problems() {
+            log::warn!("{problem}, falling back to the default presence");
+        }
+    }
+
+    /// The fields that changed in `new` but are only read at startup
+    fn needs_restart(&self, new: &Config) -> Vec<&'static str> {
+        [
+            ("discord_token", self.discord_token != new.discord_token),
+            (
+                "discord_token_file",
+                self.discord_token_file != new.discord_token_file,
+            ),
+            ("data_dir", self.data_dir != new.data_dir),
+            ("library_dir", self.library_dir != new.library_dir),
+            ("lyrics_url", self.lyrics_url != new.lyrics_url),
+        ]
+        .into_iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(field, _)| field)
+        .collect()
+    }
+
+    /// Carries over the fields from `needs_restart` so they don't change until the bot restarts
+    fn keep_startup_fields(&mut self, old: &Config) {
+        self.discord_token.clone_from(&old.discord_token);
+        self.discord_token_file.clone_from(&old.discord_token_file);
+        self.data_dir.clone_from(&old.data_dir);
+        self.library_dir.clone_from(&old.library_dir);
+        self.lyrics_url.clone_from(&old.lyrics_url);
+    }
 }
 
 fn default_bot_status() -> String {
@@ -49,3 +325,118 @@ fn default_bot_activity() -> String {
 fn default_bot_leave() -> bool {
     true
 }
+
+fn default_empty_grace_seconds() -> u32 {
+    60
+}
+
+fn default_announce_tracks() -> bool {
+    true
+}
+
+fn default_allow_duplicates() -> bool {
+    true
+}
+
+fn default_vote_skip_percent() -> u8 {
+    50
+}
+
+fn default_volume() -> u16 {
+    100
+}
+
+fn default_data_dir() -> std::path::PathBuf {
+    std::path::Path::new(".").join("data")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overridden(vars: &[(&str, &str)]) -> toml::Table {
+        let mut table = toml::Table::new();
+        apply_overrides(
+            &mut table,
+            vars.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        );
+        table
+    }
+
+    #[test]
+    fn text_fields_stay_text() {
+        let table = overridden(&[
+            ("MUSICALCAT_BOT_ACTIVITY", "1984"),
+            ("MUSICALCAT_BOT_STATUS", "true"),
+            ("MUSICALCAT_DATA_DIR", "[data]"),
+        ]);
+        assert_eq!(table["bot_activity"].as_str(), Some("1984"));
+        assert_eq!(table["bot_status"].as_str(), Some("true"));
+        assert_eq!(table["data_dir"].as_str(), Some("[data]"));
+    }
+
+    #[test]
+    fn other_fields_are_read_as_toml() {
+        let table = overridden(&[
+            ("MUSICALCAT_OWNERS", "[1234, 5678]"),
+            ("MUSICALCAT_VOTE_SKIP", "true"),
+            ("MUSICALCAT_DEFAULT_VOLUME", "80"),
+        ]);
+        assert_eq!(
+            table["owners"].as_array().map(|owners| owners.len()),
+            Some(2)
+        );
+        assert_eq!(table["vote_skip"].as_bool(), Some(true));
+        assert_eq!(table["default_volume"].as_integer(), Some(80));
+    }
+
+    #[test]
+    fn invalid_toml_is_left_as_text() {
+        let table = overridden(&[("MUSICALCAT_MAX_QUEUE_LENGTH", "lots")]);
+        assert_eq!(table["max_queue_length"].as_str(), Some("lots"));
+    }
+
+    #[test]
+    fn names_are_case_insensitive_after_the_prefix() {
+        let table = overridden(&[("MUSICALCAT_Fair_Queue", "true")]);
+        assert_eq!(table["fair_queue"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn unknown_and_unprefixed_names_are_ignored() {
+        let table = overridden(&[
+            ("MUSICALCAT_NOT_A_FIELD", "1"),
+            ("DISCORD_TOKEN", "thingy"),
+            ("HOME", "/root"),
+        ]);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn overrides_win_over_the_file() {
+        let mut table = toml::from_str::<toml::Table>("bot_activity = \"music\"").unwrap();
+        apply_overrides(
+            &mut table,
+            [("MUSICALCAT_BOT_ACTIVITY".to_string(), "jazz".to_string())],
+        );
+        assert_eq!(table["bot_activity"].as_str(), Some("jazz"));
+    }
+
+    #[test]
+    fn field_names_come_from_the_struct() {
+        let fields = config_fields();
+        assert!(fields.contains(&"discord_token"));
+        assert!(fields.contains(&"owners"));
+        assert!(fields.contains(&"lyrics_url"));
+        assert!(!fields.contains(&"not_a_field"));
+    }
+
+    #[test]
+    fn every_field_can_be_overridden() {
+        for field in config_fields() {
+            let name = format!("{ENV_PREFIX}{}", field.to_uppercase());
+            assert!(overridden(&[(&name, "1")]).contains_key(*field), "{field}");
+        }
+    }
+}
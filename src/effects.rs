@@ -0,0 +1,294 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use poise::serenity_prelude::async_trait;
+use songbird::input::core::io::MediaSource;
+use songbird::input::{AudioStream, AudioStreamError, ChildContainer, Compose, Input, RawAdapter};
+
+use crate::utils::{CustomMetadata, SourceKind};
+
+const FFMPEG_COMMAND: &str = "ffmpeg";
+const YOUTUBE_DL_COMMAND: &str = "yt-dlp";
+const SAMPLE_RATE: u32 = 48000;
+const CHANNELS: u32 = 2;
+// ffmpeg writes interleaved f32 samples
+const BYTES_PER_FRAME: u64 = 4 * CHANNELS as u64;
+// songbird's raw format starts with a 16 byte header before the samples
+const RAW_HEADER_LEN: u64 = 16;
+
+#[derive(
+    poise::ChoiceParameter,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+pub enum Filter {
+    #[name = "bassboost"]
+    BassBoost,
+    #[name = "nightcore"]
+    Nightcore,
+    #[name = "vaporwave"]
+    Vaporwave,
+    #[name = "speed"]
+    Speed,
+    #[name = "slow"]
+    Slow,
+    #[name = "pitchup"]
+    PitchUp,
+    #[name = "pitchdown"]
+    PitchDown,
+    #[name = "reverb"]
+    Reverb,
+    #[name = "mono"]
+    Mono,
+    #[name = "8d"]
+    EightD,
+}
+
+impl Filter {
+    pub fn all() -> &'static [Filter] {
+        &[
+            Filter::BassBoost,
+            Filter::Nightcore,
+            Filter::Vaporwave,
+            Filter::Speed,
+            Filter::Slow,
+            Filter::PitchUp,
+            Filter::PitchDown,
+            Filter::Reverb,
+            Filter::Mono,
+            Filter::EightD,
+        ]
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Filter::BassBoost => "Boosts the low end",
+            Filter::Nightcore => "Faster and higher pitched",
+            Filter::Vaporwave => "Slower and lower pitched",
+            Filter::Speed => "Plays 25% faster, keeping the pitch",
+            Filter::Slow => "Plays 20% slower, keeping the pitch",
+            Filter::PitchUp => "Raises the pitch, keeping the speed",
+            Filter::PitchDown => "Lowers the pitch, keeping the speed",
+            Filter::Reverb => "Adds some echo, like a big room",
+            Filter::Mono => "Mixes both channels together",
+            Filter::EightD => "Pans the sound around your head",
+        }
+    }
+
+    /// ffmpeg filter chain, the input is always resampled to 48kHz first
+    fn chain(&self) -> &'static str {
+        match self {
+            Filter::BassBoost => "bass=g=8",
+            Filter::Nightcore => "asetrate=60000,aresample=48000",
+            Filter::Vaporwave => "asetrate=38400,aresample=48000",
+            Filter::Speed => "atempo=1.25",
+            Filter::Slow => "atempo=0.8",
+            Filter::PitchUp => "asetrate=53760,aresample=48000,atempo=0.892857",
+            Filter::PitchDown => "asetrate=42720,aresample=48000,atempo=1.123596",
+            Filter::Reverb => "aecho=0.8:0.88:60|120:0.4|0.3",
+            Filter::Mono => "pan=mono|c0=0.5*c0+0.5*c1,aformat=channel_layouts=stereo",
+            Filter::EightD => "aformat=channel_layouts=stereo,apulsator=hz=0.125",
+        }
+    }
+
+    /// How much faster than the original the filtered track plays
+    fn speed(&self) -> f64 {
+        match self {
+            Filter::Nightcore | Filter::Speed => 1.25,
+            Filter::Vaporwave | Filter::Slow => 0.8,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Combined speed of `filters`, 1.0 if there are none
+pub fn speed(filters: &[Filter]) -> f64 {
+    filters.iter().map(Filter::speed).product()
+}
+
+/// Converts a position songbird reports for the track into a position in the original audio
+pub fn source_position(metadata: &CustomMetadata, position: Duration) -> Duration {
+    position.mul_f64(speed(&metadata.filters))
+}
+
+/// Converts a position in the original audio into one songbird can seek the track to
+pub fn track_position(metadata: &CustomMetadata, position: Duration) -> Duration {
+    position.div_f64(speed(&metadata.filters))
+}
+
+fn filter_graph(filters: &[Filter]) -> String {
+    std::iter::once(format!("aresample={SAMPLE_RATE}"))
+        .chain(filters.iter().map(|filter| filter.chain().to_string()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Runs the track's source through ffmpeg with `filters` applied, and notes them in its metadata
+///
+/// Tracks without a source url to hand to ffmpeg are left as they are
+pub fn apply(source: Input, metadata: &mut CustomMetadata, filters: &[Filter]) -> Input {
+    if filters.is_empty() {
+        return source;
+    }
+    let Some(url) = metadata.aux_metadata.source_url.clone() else {
+        return source;
+    };
+    metadata.filters = filters.to_vec();
+    Input::Lazy(Box::new(FilteredCompose {
+        url,
+        kind: metadata.kind,
+        live: metadata.live,
+        filters: filters.to_vec(),
+    }))
+}
+
+/// Lazily resolves the track and starts ffmpeg once songbird wants to play it
+struct FilteredCompose {
+    url: String,
+    kind: SourceKind,
+    live: bool,
+    filters: Vec<Filter>,
+}
+
+impl FilteredCompose {
+    /// ffmpeg can't read youtube pages, so yt-dlp has to find the audio stream first
+    async fn resolve(&self) -> Result<String, AudioStreamError> {
+        if self.kind != SourceKind::YoutubeDl {
+            return Ok(self.url.clone());
+        }
+        let output = tokio::process::Command::new(YOUTUBE_DL_COMMAND)
+            .args(["-f", "ba[abr>0][vcodec=none]/best", "-g", &self.url])
+            .output()
+            .await
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+        if !output.status.success() {
+            return Err(AudioStreamError::Fail(
+                format!(
+                    "{YOUTUBE_DL_COMMAND} failed with non-zero status code: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                )
+                .into(),
+            ));
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .map(|line| line.trim().to_string())
+            .ok_or_else(|| AudioStreamError::Fail("yt-dlp didn't find a stream".into()))
+    }
+}
+
+#[async_trait]
+impl Compose for FilteredCompose {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let mut source = FfmpegSource {
+            input: self.resolve().await?,
+            graph: filter_graph(&self.filters),
+            speed: speed(&self.filters),
+            seekable: !self.live,
+            process: None,
+        };
+        source
+            .start(Duration::ZERO)
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+        Ok(AudioStream {
+            input: Box::new(RawAdapter::new(source, SAMPLE_RATE, CHANNELS)),
+            hint: None,
+        })
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+}
+
+/// Raw samples coming out of ffmpeg
+///
+/// ffmpeg's output can't be seeked, so seeking restarts ffmpeg at the new position instead
+struct FfmpegSource {
+    input: String,
+    graph: String,
+    speed: f64,
+    seekable: bool,
+    process: Option<ChildContainer>,
+}
+
+impl FfmpegSource {
+    /// Starts ffmpeg at `position` in the original audio
+    fn start(&mut self, position: Duration) -> std::io::Result<()> {
+        // dropping the old one kills its process
+        self.process = None;
+
+        let mut command = Command::new(FFMPEG_COMMAND);
+        command.args(["-hide_banner", "-loglevel", "error"]);
+        if self.input.starts_with("http://") || self.input.starts_with("https://") {
+            command.args([
+                "-reconnect",
+                "1",
+                "-reconnect_streamed",
+                "1",
+                "-reconnect_delay_max",
+                "5",
+            ]);
+        }
+        if !position.is_zero() {
+            command.args(["-ss", &position.as_secs_f64().to_string()]);
+        }
+        let child = command
+            .args(["-i", &self.input, "-af", &self.graph])
+            .args(["-f", "f32le", "-ar", &SAMPLE_RATE.to_string()])
+            .args(["-ac", &CHANNELS.to_string(), "pipe:1"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        self.process = Some(child.into());
+        Ok(())
+    }
+}
+
+impl Read for FfmpegSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.process.as_mut() {
+            Some(process) => process.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Seek for FfmpegSource {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        // songbird only ever seeks to sample offsets counted from the start of its header
+        let SeekFrom::Start(offset) = pos else {
+            return Err(std::io::ErrorKind::Unsupported.into());
+        };
+        let frames = offset.saturating_sub(RAW_HEADER_LEN) / BYTES_PER_FRAME;
+        let position = Duration::from_secs_f64(frames as f64 / SAMPLE_RATE as f64);
+        self.start(position.mul_f64(self.speed))?;
+        Ok(offset)
+    }
+}
+
+impl MediaSource for FfmpegSource {
+    fn is_seekable(&self) -> bool {
+        self.seekable
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::prelude::TypeMapKey;
+use songbird::tracks::{PlayMode, TrackHandle};
+
+use crate::announcer;
+use crate::persistence;
+use crate::settings;
+use crate::utils;
+
+/// A guild whose voice channel emptied out, waiting to see if anyone comes back
+struct GracePeriod {
+    timer: tokio::task::JoinHandle<()>,
+    /// The track that was paused when everyone left, it plays again if they come back
+    paused: Option<TrackHandle>,
+}
+
+/// Keeps the bot around for a bit when its voice channel empties, in case people are just reconnecting
+#[derive(Default)]
+pub struct GracePeriods {
+    periods: std::sync::Mutex<HashMap<serenity::GuildId, GracePeriod>>,
+}
+
+impl GracePeriods {
+    fn is_waiting(&self, guild_id: serenity::GuildId) -> bool {
+        self.periods.lock().unwrap().contains_key(&guild_id)
+    }
+
+    fn start(
+        &self,
+        ctx: &serenity::Context,
+        guild_id: serenity::GuildId,
+        grace: Duration,
+        paused: Option<TrackHandle>,
+    ) {
+        let ctx = ctx.clone();
+        let timer = tokio::spawn(async move {
+            tokio::time::sleep(grace).await;
+            get_grace_periods(&ctx)
+                .await
+                .periods
+                .lock()
+                .unwrap()
+                .remove(&guild_id);
+            if let Err(e) = leave_if_empty(&ctx, guild_id).await {
+                log::error!("Can't leave the empty voice channel: {:?}", e);
+            }
+        });
+        let previous = self
+            .periods
+            .lock()
+            .unwrap()
+            .insert(guild_id, GracePeriod { timer, paused });
+        if let Some(previous) = previous {
+            previous.timer.abort();
+        }
+    }
+
+    /// Stops waiting, returns the track to play again if there was one
+    fn end(&self, guild_id: serenity::GuildId) -> Option<TrackHandle> {
+        let period = self.periods.lock().unwrap().remove(&guild_id)?;
+        period.timer.abort();
+        period.paused
+    }
+}
+
+pub struct GracePeriodsKey;
+
+impl TypeMapKey for GracePeriodsKey {
+    type Value = Arc<GracePeriods>;
+}
+
+pub async fn get_grace_periods(ctx: &serenity::Context) -> Arc<GracePeriods> {
+    let data = ctx.data.read().await;
+    data.get::<GracePeriodsKey>().cloned().unwrap()
+}
+
+/// Pauses the music and starts the guild's grace period, or leaves right away if it has none
+pub async fn channel_emptied(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+) -> eyre::Result<()> {
+    let grace_periods = get_grace_periods(ctx).await;
+    if grace_periods.is_waiting(guild_id) {
+        return Ok(());
+    }
+    let Some(grace) = settings::get_guild_settings(ctx, guild_id)
+        .await
+        .empty_grace_period()
+    else {
+        return leave(ctx, guild_id).await;
+    };
+
+    let manager = songbird::get(ctx).await.unwrap().clone();
+    let Some(call) = manager.get(guild_id) else {
+        return Ok(());
+    };
+    let current = call.lock().await.queue().current();
+    // tracks that were paused already stay paused when people come back
+    let mut paused = None;
+    if let Some(trackhandle) = current {
+        if trackhandle
+            .get_info()
+            .await
+            .is_ok_and(|info| info.playing == PlayMode::Play)
+        {
+            trackhandle.pause()?;
+            paused = Some(trackhandle);
+        }
+    }
+
+    grace_periods.start(ctx, guild_id, grace, paused);
+
+    Ok(())
+}
+
+/// Ends the guild's grace period if it's in one, and plays what was paused when everyone left
+pub async fn listener_returned(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+) -> eyre::Result<()> {
+    let Some(trackhandle) = get_grace_periods(ctx).await.end(guild_id) else {
+        return Ok(());
+    };
+
+    let manager = songbird::get(ctx).await.unwrap().clone();
+    let Some(call) = manager.get(guild_id) else {
+        return Ok(());
+    };
+    // somebody could have moved the queue along in the meantime
+    let current = call.lock().await.queue().current();
+    if current.is_some_and(|current| current.uuid() == trackhandle.uuid()) {
+        trackhandle.play()?;
+    }
+
+    Ok(())
+}
+
+async fn leave_if_empty(ctx: &serenity::Context, guild_id: serenity::GuildId) -> eyre::Result<()> {
+    // it left some other way in the meantime
+    let Some(listeners) = utils::listeners(ctx, guild_id).await? else {
+        return Ok(());
+    };
+    if !listeners.is_empty() {
+        return Ok(());
+    }
+    leave(ctx, guild_id).await
+}
+
+/// Leaves the guild's voice channel, keeping its queue first if the guild wants that
+async fn leave(ctx: &serenity::Context, guild_id: serenity::GuildId) -> eyre::Result<()> {
+    let manager = songbird::get(ctx).await.unwrap().clone();
+    let Some(call) = manager.get(guild_id) else {
+        return Ok(());
+    };
+
+    let kept = if settings::get_guild_settings(ctx, guild_id)
+        .await
+        .keep_queue_on_leave()
+    {
+        persistence::keep_queue(ctx, guild_id, &call).await?
+    } else {
+        false
+    };
+
+    manager.remove(guild_id).await?;
+
+    if kept {
+        if let Some(channel_id) = announcer::get_announcer(ctx).await.channel(guild_id) {
+            channel_id
+                .say(
+                    ctx,
+                    "Everyone left, so I did too! Use /resume to pick the queue back up",
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,11 +1,53 @@
 use super::{Data, Error};
 use poise::serenity_prelude::{self as serenity, ActivityData};
 
-use super::config::get_config;
+use super::config::{get_config, Config};
+use super::grace;
+use super::persistence;
+use super::settings;
+use super::utils;
+
+/// The status and activity config.toml asks for
+pub fn presence(config: &Config) -> (ActivityData, serenity::OnlineStatus) {
+    let status = match config.bot_status.to_lowercase().as_str() {
+        "online" => serenity::OnlineStatus::Online,
+        "idle" => serenity::OnlineStatus::Idle,
+        "donotdisturb" => serenity::OnlineStatus::DoNotDisturb,
+        "offline" => serenity::OnlineStatus::Offline,
+        "invisible" => serenity::OnlineStatus::Invisible,
+        _ => serenity::OnlineStatus::Online,
+    };
+
+    let mut activity_string = config.bot_activity.clone();
+    activity_string.truncate(127);
+
+    let activity = match config.bot_activity_type.to_lowercase().as_str() {
+        "listening" => ActivityData::listening(activity_string),
+        "streaming" => ActivityData::streaming(activity_string, config.bot_activity_url.as_str())
+            .unwrap_or_else(|_| ActivityData::listening("music")),
+        "playing" => ActivityData::playing(activity_string),
+        "watching" => ActivityData::watching(activity_string),
+        "competing" => ActivityData::competing(activity_string),
+        _ => ActivityData::listening("music"),
+    };
+
+    (activity, status)
+}
+
+/// Sets the presence config.toml asks for on every shard, after it's reloaded
+pub async fn apply_presence(shard_manager: &serenity::ShardManager) {
+    let (activity, status) = presence(&get_config());
+    for runner in shard_manager.runners.lock().await.values() {
+        runner
+            .runner_tx
+            .set_presence(Some(activity.clone()), status);
+    }
+}
 
 pub struct Handler {
     pub options: poise::FrameworkOptions<Data, Error>,
     pub shard_manager: std::sync::Mutex<Option<std::sync::Arc<serenity::ShardManager>>>,
+    pub queues_restored: std::sync::atomic::AtomicBool,
 }
 #[serenity::async_trait]
 impl serenity::EventHandler for Handler {
@@ -15,37 +57,24 @@ impl serenity::EventHandler for Handler {
             log::error!("{:?}", e)
         };
 
-        let config = get_config();
-
         let application_id = ctx.http.application_id().unwrap_or_default();
 
         println!("Ready! Invite the bot with https://discordapp.com/oauth2/authorize?client_id={application_id}&scope=bot%20applications.commands&permissions=36700160");
 
-        let status = match config.bot_status.as_str() {
-            "online" => serenity::OnlineStatus::Online,
-            "idle" => serenity::OnlineStatus::Idle,
-            "donotdisturb" => serenity::OnlineStatus::DoNotDisturb,
-            "offline" => serenity::OnlineStatus::Offline,
-            "invisible" => serenity::OnlineStatus::Invisible,
-            _ => serenity::OnlineStatus::Online,
-        };
-
-        let mut activity_string = config.bot_activity.clone();
-        activity_string.truncate(127);
-
-        let activity = match config.bot_activity_type.as_str() {
-            "listening" => ActivityData::listening(activity_string),
-            "streaming" => {
-                ActivityData::streaming(activity_string, config.bot_activity_url.as_str()).unwrap()
-            }
-            "playing" => ActivityData::playing(activity_string),
-            "watching" => ActivityData::watching(activity_string),
-            "competing" => ActivityData::competing(activity_string),
-            _ => ActivityData::listening("music"),
-        };
-
+        let (activity, status) = presence(&get_config());
         ctx.set_presence(Some(activity), status);
 
+        // ready fires again on reconnects, only restore once
+        if !self
+            .queues_restored
+            .swap(true, std::sync::atomic::Ordering::SeqCst)
+        {
+            if let Err(e) = persistence::restore_queues(&ctx).await {
+                log::error!("{:?}", e)
+            };
+            tokio::spawn(persistence::snapshot_loop(ctx.clone()));
+        }
+
         let shard_manager = (*self.shard_manager.lock().unwrap()).clone().unwrap();
         let framework_data = poise::FrameworkContext {
             bot_id: Default::default(),
@@ -107,12 +136,9 @@ impl serenity::EventHandler for Handler {
         old: Option<serenity::VoiceState>,
         new: serenity::VoiceState,
     ) {
-        let config = get_config();
-        if config.bot_leave_on_empty {
-            if let Err(e) = handle_voice_state_update(&ctx, &new).await {
-                log::error!("{:?}", e)
-            };
-        }
+        if let Err(e) = handle_voice_state_update(&ctx, &new).await {
+            log::error!("{:?}", e)
+        };
         let shard_manager = (*self.shard_manager.lock().unwrap()).clone().unwrap();
         let framework_data = poise::FrameworkContext {
             bot_id: Default::default(),
@@ -138,39 +164,22 @@ async fn handle_voice_state_update(
         return Ok(());
     };
 
-    let manager = songbird::get(ctx).await.unwrap().clone();
-
-    let Some(call) = manager.get(guild_id) else {
-        return Ok(());
-    };
-
-    let Some(cur_channel) = call
-        .lock()
+    if !settings::get_guild_settings(ctx, guild_id)
         .await
-        .current_channel()
-        .map(|item| item.0.into())
-    else {
+        .leave_on_empty()
+    {
         return Ok(());
-    };
-
-    let channels = guild_id.channels(&ctx.http).await?;
+    }
 
-    let Some(channel) = channels.get(&cur_channel).cloned() else {
+    let Some(listeners) = utils::listeners(ctx, guild_id).await? else {
         return Ok(());
     };
 
-    //find non-botted users in the channel, if there isn't, then disconnect
+    //if there are no non-botted users in the channel, wait a bit before leaving in case they come back
 
-    if !channel
-        .members(&ctx.cache)
-        .unwrap()
-        .into_iter()
-        .any(|member| !member.user.bot)
-    {
-        if let Err(e) = manager.remove(guild_id).await {
-            log::error!("{:?}", e)
-        }
-    };
-
-    Ok(())
+    if listeners.is_empty() {
+        grace::channel_emptied(ctx, guild_id).await
+    } else {
+        grace::listener_returned(ctx, guild_id).await
+    }
 }
@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use poise::serenity_prelude as serenity;
+use songbird::events::{Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent};
+use songbird::tracks::TrackHandle;
+
+use crate::storage;
+use crate::utils::{CustomMetadata, SavedTrack};
+
+/// How many played tracks are kept per guild
+const MAX_HISTORY: usize = 100;
+
+// every guild gets its own file, but recording is rare enough for a single lock
+static HISTORY_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// A track that finished playing, or got skipped
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct PlayedTrack {
+    pub track: SavedTrack,
+    /// When it stopped playing
+    pub played_at: SystemTime,
+    /// How long it was actually listened to, pauses aside
+    pub played_for: Duration,
+}
+
+/// Tracks a guild played, newest first
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct History {
+    pub tracks: VecDeque<PlayedTrack>,
+}
+
+fn history_path(guild_id: serenity::GuildId) -> PathBuf {
+    storage::data_path("history").join(format!("{guild_id}.json"))
+}
+
+pub async fn load(guild_id: serenity::GuildId) -> eyre::Result<History> {
+    let _guard = HISTORY_LOCK.lock().await;
+    Ok(storage::load(&history_path(guild_id))
+        .await?
+        .unwrap_or_default())
+}
+
+/// Modifies the guild's history and writes it back to disk
+pub async fn modify<F, O>(guild_id: serenity::GuildId, func: F) -> eyre::Result<O>
+where
+    F: FnOnce(&mut History) -> O,
+{
+    let _guard = HISTORY_LOCK.lock().await;
+    let path = history_path(guild_id);
+    let mut history = storage::load(&path).await?.unwrap_or_default();
+    let output = func(&mut history);
+    storage::save(&path, &history).await?;
+    Ok(output)
+}
+
+/// Records tracks once they end
+struct HistoryRecorder {
+    guild_id: serenity::GuildId,
+}
+
+#[serenity::async_trait]
+impl VoiceEventHandler for HistoryRecorder {
+    async fn act(&self, event: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(tracks) = event else {
+            return None;
+        };
+
+        for (state, trackhandle) in tracks.iter() {
+            // tracks that were removed before their turn never played
+            if state.play_time.is_zero() {
+                continue;
+            }
+            if trackhandle
+                .data::<CustomMetadata>()
+                .discarded
+                .load(std::sync::atomic::Ordering::Relaxed)
+            {
+                continue;
+            }
+            let Some(track) = SavedTrack::from_handle(trackhandle) else {
+                continue;
+            };
+
+            let played = PlayedTrack {
+                track,
+                played_at: SystemTime::now(),
+                played_for: state.play_time,
+            };
+            let result = modify(self.guild_id, |history| {
+                history.tracks.push_front(played);
+                history.tracks.truncate(MAX_HISTORY);
+            })
+            .await;
+            if let Err(e) = result {
+                log::error!("Can't record the played track: {:?}", e);
+            }
+        }
+
+        None
+    }
+}
+
+/// Adds the track to its guild's history when it ends
+pub fn add_events(
+    guild_id: serenity::GuildId,
+    trackhandle: &TrackHandle,
+) -> songbird::error::TrackResult<()> {
+    trackhandle.add_event(Event::Track(TrackEvent::End), HistoryRecorder { guild_id })
+}
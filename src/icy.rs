@@ -0,0 +1,258 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use songbird::events::{Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent};
+use songbird::input::AuxMetadata;
+use songbird::tracks::TrackHandle;
+
+use crate::utils::{self, CustomMetadata, SourceKind};
+
+/// How long a server gets to answer before the url is handed to yt-dlp instead
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Sites yt-dlp handles, which are never plain streams so they aren't probed
+const YTDL_HOSTS: &[&str] = &[
+    "youtube.com",
+    "youtu.be",
+    "soundcloud.com",
+    "bandcamp.com",
+    "twitch.tv",
+    "vimeo.com",
+];
+
+/// Checks whether `url` points straight at an audio stream (like an icecast/shoutcast radio)
+/// rather than a page yt-dlp should resolve, returns the stream's metadata if it is
+pub async fn probe(http_client: &reqwest::Client, url: &str) -> Option<AuxMetadata> {
+    let parsed = reqwest::Url::parse(url).ok()?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return None;
+    }
+    let host = parsed.host_str()?;
+    if YTDL_HOSTS
+        .iter()
+        .any(|ytdl_host| host == *ytdl_host || host.ends_with(&format!(".{ytdl_host}")))
+    {
+        return None;
+    }
+
+    // only the headers are read, the body is dropped with the response
+    let response = http_client
+        .get(url)
+        .header("Icy-MetaData", "1")
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?;
+    let headers = response.headers();
+
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+
+    let is_icy = headers.keys().any(|key| key.as_str().starts_with("icy-"));
+    let is_audio = header("content-type")
+        .is_some_and(|kind| kind.starts_with("audio/") || kind.starts_with("application/ogg"));
+
+    // a plain audio file has a length, a stream doesn't
+    let is_stream = is_audio && response.content_length().is_none();
+    if !(is_icy || is_stream) {
+        return None;
+    }
+
+    Some(AuxMetadata {
+        title: header("icy-name").or_else(|| Some(url.to_string())),
+        channel: header("icy-description").or_else(|| header("icy-genre")),
+        source_url: Some(url.to_string()),
+        ..Default::default()
+    })
+}
+
+/// The title watcher of a radio track, shared by its start and end events
+#[derive(Clone, Default)]
+struct TitleWatcher {
+    task: Arc<Mutex<Option<tokio::task::JoinHandle<()>>>>,
+}
+
+impl TitleWatcher {
+    /// Starts following the radio's titles, unless that's already happening (like after a pause)
+    fn start(&self, http_client: reqwest::Client, trackhandle: TrackHandle) {
+        let mut task = self.task.lock().unwrap();
+        if task.as_ref().is_some_and(|task| !task.is_finished()) {
+            return;
+        }
+        let Some(url) = trackhandle
+            .data::<CustomMetadata>()
+            .aux_metadata
+            .source_url
+            .clone()
+        else {
+            return;
+        };
+        *task = Some(tokio::spawn(async move {
+            if let Err(e) = watch_stream_titles(http_client, url, trackhandle).await {
+                log::warn!("Stopped following stream titles: {:?}", e)
+            }
+        }));
+    }
+
+    fn stop(&self) {
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+    }
+}
+
+struct TitleWatcherEvent {
+    ctx: serenity::Context,
+    watcher: TitleWatcher,
+    ended: bool,
+}
+
+#[serenity::async_trait]
+impl VoiceEventHandler for TitleWatcherEvent {
+    async fn act(&self, event: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(tracks) = event else {
+            return None;
+        };
+        if self.ended {
+            self.watcher.stop();
+            return None;
+        }
+
+        let http_client = {
+            let data = self.ctx.data.read().await;
+            data.get::<utils::HttpKey>().cloned().unwrap()
+        };
+        for (_, trackhandle) in tracks.iter() {
+            self.watcher
+                .start(http_client.clone(), (*trackhandle).clone());
+        }
+
+        None
+    }
+}
+
+/// Follows a radio's stream titles while the track plays, tracks that aren't radios are left alone
+pub fn add_events(
+    ctx: &serenity::Context,
+    trackhandle: &TrackHandle,
+) -> songbird::error::TrackResult<()> {
+    if trackhandle.data::<CustomMetadata>().kind != SourceKind::Http {
+        return Ok(());
+    }
+    let watcher = TitleWatcher::default();
+    trackhandle.add_event(
+        Event::Track(TrackEvent::Play),
+        TitleWatcherEvent {
+            ctx: ctx.clone(),
+            watcher: watcher.clone(),
+            ended: false,
+        },
+    )?;
+    trackhandle.add_event(
+        Event::Track(TrackEvent::End),
+        TitleWatcherEvent {
+            ctx: ctx.clone(),
+            watcher,
+            ended: true,
+        },
+    )
+}
+
+enum IcyState {
+    Audio(usize),
+    Length,
+    Metadata(usize),
+}
+
+/// Follows the radio's stream titles and stores them in the track's metadata, until the track is gone
+///
+/// songbird's own request doesn't ask for icy metadata (it would end up in the audio), so this opens a second one
+async fn watch_stream_titles(
+    http_client: reqwest::Client,
+    url: String,
+    trackhandle: TrackHandle,
+) -> eyre::Result<()> {
+    let mut response = http_client
+        .get(&url)
+        .header("Icy-MetaData", "1")
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let Some(metaint) = response
+        .headers()
+        .get("icy-metaint")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok())
+    else {
+        return Ok(());
+    };
+
+    let metadata = trackhandle.data::<CustomMetadata>();
+    let mut state = IcyState::Audio(metaint);
+    let mut block = Vec::new();
+
+    while let Some(chunk) = response.chunk().await? {
+        let mut chunk = &chunk[..];
+        while !chunk.is_empty() {
+            state = match state {
+                IcyState::Audio(left) => {
+                    let read = left.min(chunk.len());
+                    chunk = &chunk[read..];
+                    if read == left {
+                        IcyState::Length
+                    } else {
+                        IcyState::Audio(left - read)
+                    }
+                }
+                IcyState::Length => {
+                    let len = chunk[0] as usize * 16;
+                    chunk = &chunk[1..];
+                    block.clear();
+                    if len == 0 {
+                        IcyState::Audio(metaint)
+                    } else {
+                        IcyState::Metadata(len)
+                    }
+                }
+                IcyState::Metadata(left) => {
+                    let read = left.min(chunk.len());
+                    block.extend_from_slice(&chunk[..read]);
+                    chunk = &chunk[read..];
+                    if read < left {
+                        IcyState::Metadata(left - read)
+                    } else {
+                        if trackhandle.get_info().await.is_err() {
+                            // the track ended or got removed
+                            return Ok(());
+                        }
+                        if let Some(title) = parse_stream_title(&block) {
+                            *metadata.stream_title.lock().unwrap() = Some(title);
+                        }
+                        IcyState::Audio(metaint)
+                    }
+                }
+            };
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_stream_title(block: &[u8]) -> Option<String> {
+    let block = String::from_utf8_lossy(block);
+    let title = regex::Regex::new(r"StreamTitle='(.*?)';")
+        .unwrap()
+        .captures(&block)?
+        .get(1)?
+        .as_str()
+        .trim()
+        .to_string();
+    (!title.is_empty()).then_some(title)
+}
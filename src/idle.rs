@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::prelude::TypeMapKey;
+use songbird::events::{Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent};
+use songbird::tracks::{PlayMode, TrackHandle};
+
+use crate::announcer;
+use crate::settings;
+
+/// Timers that make the bot leave guilds where nothing has played for a while
+#[derive(Default)]
+pub struct IdleTimers {
+    timers: std::sync::Mutex<HashMap<serenity::GuildId, tokio::task::JoinHandle<()>>>,
+}
+
+impl IdleTimers {
+    /// Leaves the guild's voice channel once `timeout` passes, unless something is playing by then
+    ///
+    /// Replaces the guild's running timer if there is one
+    fn start(&self, ctx: &serenity::Context, guild_id: serenity::GuildId, timeout: Duration) {
+        let ctx = ctx.clone();
+        let timer = tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            if let Err(e) = leave_if_idle(&ctx, guild_id, timeout).await {
+                log::error!("Can't leave the idle voice channel: {:?}", e);
+            }
+        });
+        if let Some(previous) = self.timers.lock().unwrap().insert(guild_id, timer) {
+            previous.abort();
+        }
+    }
+
+    fn cancel(&self, guild_id: serenity::GuildId) {
+        if let Some(timer) = self.timers.lock().unwrap().remove(&guild_id) {
+            timer.abort();
+        }
+    }
+}
+
+pub struct IdleTimersKey;
+
+impl TypeMapKey for IdleTimersKey {
+    type Value = Arc<IdleTimers>;
+}
+
+pub async fn get_idle_timers(ctx: &serenity::Context) -> Arc<IdleTimers> {
+    let data = ctx.data.read().await;
+    data.get::<IdleTimersKey>().cloned().unwrap()
+}
+
+/// Starts the guild's idle timer, if the guild has an idle timeout
+pub async fn start(ctx: &serenity::Context, guild_id: serenity::GuildId) {
+    let Some(timeout) = settings::get_guild_settings(ctx, guild_id)
+        .await
+        .idle_timeout()
+    else {
+        return;
+    };
+    get_idle_timers(ctx).await.start(ctx, guild_id, timeout);
+}
+
+/// Stops the guild's idle timer, something is playing again
+pub async fn cancel(ctx: &serenity::Context, guild_id: serenity::GuildId) {
+    get_idle_timers(ctx).await.cancel(guild_id);
+}
+
+fn describe_timeout(timeout: Duration) -> String {
+    match timeout.as_secs() / 60 {
+        1 => "a minute".to_string(),
+        minutes => format!("{minutes} minutes"),
+    }
+}
+
+async fn leave_if_idle(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    timeout: Duration,
+) -> eyre::Result<()> {
+    let manager = songbird::get(ctx).await.unwrap().clone();
+    // it left some other way in the meantime
+    let Some(call) = manager.get(guild_id) else {
+        return Ok(());
+    };
+    {
+        let handler = call.lock().await;
+        if handler.current_channel().is_none() {
+            return Ok(());
+        }
+        // something started playing without going through /play or /resume, like autoplay
+        if let Some(trackhandle) = handler.queue().current() {
+            if trackhandle
+                .get_info()
+                .await
+                .is_ok_and(|info| info.playing == PlayMode::Play)
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    manager.remove(guild_id).await?;
+
+    if let Some(channel_id) = announcer::get_announcer(ctx).await.channel(guild_id) {
+        channel_id
+            .say(
+                ctx,
+                format!(
+                    "Nothing played for {}, so I left the voice channel!",
+                    describe_timeout(timeout)
+                ),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Starts the idle timer once the last track of a guild's queue ends
+struct IdleWatcher {
+    ctx: serenity::Context,
+    guild_id: serenity::GuildId,
+}
+
+#[serenity::async_trait]
+impl VoiceEventHandler for IdleWatcher {
+    async fn act(&self, event: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(tracks) = event else {
+            return None;
+        };
+        let manager = songbird::get(&self.ctx).await.unwrap().clone();
+        // tracks end when the bot leaves too, there's nothing to time then
+        let call = manager.get(self.guild_id)?;
+        {
+            let handler = call.lock().await;
+            handler.current_channel()?;
+            let queue = handler.queue().current_queue();
+            if queue.iter().any(|handle| {
+                tracks
+                    .iter()
+                    .all(|(_, ended)| ended.uuid() != handle.uuid())
+            }) {
+                return None;
+            }
+        }
+
+        start(&self.ctx, self.guild_id).await;
+
+        None
+    }
+}
+
+/// Starts the idle timer when the track ends, if nothing is left to play after it
+pub fn add_events(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    trackhandle: &TrackHandle,
+) -> songbird::error::TrackResult<()> {
+    trackhandle.add_event(
+        Event::Track(TrackEvent::End),
+        IdleWatcher {
+            ctx: ctx.clone(),
+            guild_id,
+        },
+    )
+}
@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{DebounceEventResult, Debouncer};
+use poise::serenity_prelude::prelude::TypeMapKey;
+use songbird::input::AuxMetadata;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey, StandardVisualKey};
+use symphonia::core::probe::Hint;
+
+use crate::utils::{CustomMetadata, SourceKind};
+
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "flac", "ogg", "oga", "opus", "m4a", "mp4", "aac", "wav", "mka", "mkv", "webm",
+];
+const COVER_FILES: &[&str] = &["cover.jpg", "cover.png", "folder.jpg", "folder.png"];
+
+#[derive(Clone, Debug, Default)]
+pub struct LibraryTrack {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub date: Option<String>,
+    pub track_number: Option<u32>,
+    pub duration: Option<Duration>,
+}
+
+impl LibraryTrack {
+    pub fn title(&self) -> String {
+        self.title.clone().unwrap_or_else(|| {
+            self.path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or("Untitled".to_string())
+        })
+    }
+
+    pub fn aux_metadata(&self) -> AuxMetadata {
+        AuxMetadata {
+            track: self.title.clone(),
+            artist: self.artist.clone(),
+            album: self.album.clone(),
+            date: self.date.clone(),
+            channel: self.artist.clone(),
+            duration: self.duration,
+            source_url: Some(self.path.to_string_lossy().to_string()),
+            title: Some(self.title()),
+            ..Default::default()
+        }
+    }
+
+    pub fn into_source(self, requested_by: String) -> (songbird::input::Input, CustomMetadata) {
+        let aux_metadata = self.aux_metadata();
+        (
+            songbird::input::File::new(self.path).into(),
+            CustomMetadata {
+                aux_metadata,
+                requested_by,
+                kind: SourceKind::File,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn matches(&self, words: &[String]) -> bool {
+        let haystack = format!(
+            "{} {} {} {}",
+            self.title(),
+            self.artist.as_deref().unwrap_or_default(),
+            self.album.as_deref().unwrap_or_default(),
+            self.path.to_string_lossy()
+        )
+        .to_lowercase();
+        words.iter().all(|word| haystack.contains(word))
+    }
+}
+
+/// Index of the music folder on the bot's host, kept up to date by a file watcher
+pub struct Library {
+    root: PathBuf,
+    tracks: RwLock<BTreeMap<PathBuf, LibraryTrack>>,
+    watcher: std::sync::Mutex<Option<Debouncer<RecommendedWatcher>>>,
+}
+
+impl Library {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            tracks: RwLock::new(BTreeMap::new()),
+            watcher: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Starts watching the folder, then indexes it in the background
+    pub fn start(self: &Arc<Self>) -> eyre::Result<()> {
+        let library = Arc::downgrade(self);
+        let mut debouncer = notify_debouncer_mini::new_debouncer(
+            Duration::from_secs(2),
+            move |result: DebounceEventResult| {
+                let Some(library) = library.upgrade() else {
+                    return;
+                };
+                match result {
+                    Ok(events) => events
+                        .into_iter()
+                        .for_each(|event| library.refresh(&event.path)),
+                    Err(e) => log::error!("{:?}", e),
+                }
+            },
+        )?;
+        debouncer
+            .watcher()
+            .watch(&self.root, RecursiveMode::Recursive)?;
+        *self.watcher.lock().unwrap() = Some(debouncer);
+
+        let library = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let root = library.root.clone();
+            library.refresh(&root);
+            log::info!("Indexed {} tracks from the library", library.track_count());
+        });
+
+        Ok(())
+    }
+
+    pub fn track_count(&self) -> usize {
+        self.tracks.read().unwrap().len()
+    }
+
+    /// Re-reads whatever is at `path`, or forgets about it if it's gone
+    fn refresh(&self, path: &Path) {
+        if path.is_dir() {
+            walkdir::WalkDir::new(path)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_file())
+                .for_each(|entry| self.index_file(entry.path()));
+        } else if path.is_file() {
+            self.index_file(path);
+        } else {
+            self.tracks
+                .write()
+                .unwrap()
+                .retain(|track_path, _| !track_path.starts_with(path));
+        }
+    }
+
+    fn index_file(&self, path: &Path) {
+        if !is_audio_file(path) {
+            return;
+        }
+        match read_tags(path) {
+            Ok(track) => {
+                self.tracks
+                    .write()
+                    .unwrap()
+                    .insert(path.to_path_buf(), track);
+            }
+            Err(e) => log::warn!("Can't read tags from {:?}: {:?}", path, e),
+        }
+    }
+
+    /// Tracks that contain every word of `query` in their title, artist, album or path
+    pub fn search(&self, query: &str) -> Vec<LibraryTrack> {
+        let words = query
+            .split_whitespace()
+            .map(|word| word.to_lowercase())
+            .collect::<Vec<_>>();
+        self.tracks
+            .read()
+            .unwrap()
+            .values()
+            .filter(|track| track.matches(&words))
+            .cloned()
+            .collect()
+    }
+
+    /// Every track of the first album matching `query`, in album order
+    pub fn album(&self, query: &str) -> Vec<LibraryTrack> {
+        let query = query.to_lowercase();
+        let tracks = self.tracks.read().unwrap();
+        let Some(album) = tracks
+            .values()
+            .filter_map(|track| track.album.as_ref())
+            .find(|album| album.to_lowercase().contains(&query))
+        else {
+            return Vec::new();
+        };
+        let mut album_tracks = tracks
+            .values()
+            .filter(|track| track.album.as_ref() == Some(album))
+            .cloned()
+            .collect::<Vec<_>>();
+        album_tracks.sort_by_key(|track| track.track_number);
+        album_tracks
+    }
+
+    /// Every track of the first artist matching `query`, ordered by album
+    pub fn artist(&self, query: &str) -> Vec<LibraryTrack> {
+        let query = query.to_lowercase();
+        let tracks = self.tracks.read().unwrap();
+        let Some(artist) = tracks
+            .values()
+            .filter_map(|track| track.artist.as_ref())
+            .find(|artist| artist.to_lowercase().contains(&query))
+        else {
+            return Vec::new();
+        };
+        let mut artist_tracks = tracks
+            .values()
+            .filter(|track| track.artist.as_ref() == Some(artist))
+            .cloned()
+            .collect::<Vec<_>>();
+        artist_tracks.sort_by(|a, b| (&a.album, a.track_number).cmp(&(&b.album, b.track_number)));
+        artist_tracks
+    }
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+fn probe(path: &Path) -> eyre::Result<symphonia::core::probe::ProbeResult> {
+    let file = std::fs::File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+    Ok(symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?)
+}
+
+/// Container tags (like vorbis comments) come first, then the ones found while probing (like id3)
+fn revisions(probed: &mut symphonia::core::probe::ProbeResult) -> Vec<MetadataRevision> {
+    let mut revisions = Vec::new();
+    if let Some(revision) = probed.format.metadata().current() {
+        revisions.push(revision.clone());
+    }
+    if let Some(revision) = probed
+        .metadata
+        .get()
+        .as_ref()
+        .and_then(|metadata| metadata.current())
+    {
+        revisions.push(revision.clone());
+    }
+    revisions
+}
+
+fn read_tags(path: &Path) -> eyre::Result<LibraryTrack> {
+    let mut probed = probe(path)?;
+
+    let duration = probed.format.default_track().and_then(|track| {
+        let time = track
+            .codec_params
+            .time_base?
+            .calc_time(track.codec_params.n_frames?);
+        Some(Duration::from_secs_f64(time.seconds as f64 + time.frac))
+    });
+
+    let revisions = revisions(&mut probed);
+    let tag = |key: StandardTagKey| {
+        revisions
+            .iter()
+            .flat_map(|revision| revision.tags())
+            .find(|tag| tag.std_key == Some(key))
+            .map(|tag| tag.value.to_string())
+    };
+
+    Ok(LibraryTrack {
+        path: path.to_path_buf(),
+        title: tag(StandardTagKey::TrackTitle),
+        artist: tag(StandardTagKey::Artist).or_else(|| tag(StandardTagKey::AlbumArtist)),
+        album: tag(StandardTagKey::Album),
+        date: tag(StandardTagKey::Date),
+        // track numbers are sometimes written as "3/12"
+        track_number: tag(StandardTagKey::TrackNumber).and_then(|number| {
+            number
+                .split('/')
+                .next()
+                .and_then(|number| number.trim().parse().ok())
+        }),
+        duration,
+    })
+}
+
+/// Reads the track's cover art, either embedded in the file or from a cover image next to it
+pub fn read_cover(path: &Path) -> Option<(Vec<u8>, String)> {
+    let embedded = probe(path).ok().and_then(|mut probed| {
+        let revisions = revisions(&mut probed);
+        let visuals = revisions
+            .iter()
+            .flat_map(|revision| revision.visuals())
+            .collect::<Vec<_>>();
+        visuals
+            .iter()
+            .find(|visual| visual.usage == Some(StandardVisualKey::FrontCover))
+            .or(visuals.first())
+            .map(|visual| {
+                let ext = match visual.media_type.as_str() {
+                    "image/png" => "png",
+                    _ => "jpg",
+                };
+                (visual.data.to_vec(), format!("cover.{ext}"))
+            })
+    });
+
+    embedded.or_else(|| {
+        let dir = path.parent()?;
+        COVER_FILES.iter().find_map(|name| {
+            let data = std::fs::read(dir.join(name)).ok()?;
+            let ext = Path::new(name).extension()?.to_str()?;
+            Some((data, format!("cover.{ext}")))
+        })
+    })
+}
+
+pub struct LibraryKey;
+
+impl TypeMapKey for LibraryKey {
+    type Value = Arc<Library>;
+}
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use songbird::input::Input;
+
+use crate::permissions;
+use crate::settings::{self, GuildSettings};
+use crate::utils::CustomMetadata;
+use crate::Context;
+use crate::Error;
+
+/// Why a track can't be queued
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Refusal {
+    TooLong,
+    Duplicate,
+    UserFull,
+    QueueFull,
+}
+
+/// What a guild lets its members queue, `None` means there's no limit
+struct Limits {
+    max_track_length: Option<Duration>,
+    max_user_tracks: Option<usize>,
+    max_queue_length: Option<usize>,
+    allow_duplicates: bool,
+}
+
+impl Limits {
+    fn new(guild_settings: &GuildSettings) -> Self {
+        Self {
+            max_track_length: guild_settings.max_track_length(),
+            max_user_tracks: guild_settings.max_user_tracks(),
+            max_queue_length: guild_settings.max_queue_length(),
+            allow_duplicates: guild_settings.allow_duplicates(),
+        }
+    }
+
+    /// Checks a track against what's queued already, `queued` includes the tracks let through before it
+    fn check(
+        &self,
+        metadata: &CustomMetadata,
+        queued: &[(Option<String>, String)],
+    ) -> Result<(), Refusal> {
+        // livestreams and radios have no length to go by
+        if let (Some(max), Some(duration)) = (self.max_track_length, metadata.aux_metadata.duration)
+        {
+            if duration > max {
+                return Err(Refusal::TooLong);
+            }
+        }
+        if !self.allow_duplicates
+            && metadata.aux_metadata.source_url.is_some()
+            && queued
+                .iter()
+                .any(|(url, _)| *url == metadata.aux_metadata.source_url)
+        {
+            return Err(Refusal::Duplicate);
+        }
+        if let Some(max) = self.max_user_tracks {
+            let user_tracks = queued
+                .iter()
+                .filter(|(_, requested_by)| *requested_by == metadata.requested_by)
+                .count();
+            if user_tracks >= max {
+                return Err(Refusal::UserFull);
+            }
+        }
+        if let Some(max) = self.max_queue_length {
+            if queued.len() >= max {
+                return Err(Refusal::QueueFull);
+            }
+        }
+        Ok(())
+    }
+
+    /// Explains why a single track was refused
+    fn explain(&self, refusal: Refusal, title: &str) -> String {
+        match refusal {
+            Refusal::TooLong => format!(
+                "**{title}** is too long, tracks can be {} minutes at most here!",
+                self.max_track_length.unwrap_or_default().as_secs() / 60
+            ),
+            Refusal::Duplicate => format!("**{title}** is already in the queue!"),
+            Refusal::UserFull => format!(
+                "You already have {} tracks in the queue, that's as many as anyone can have here!",
+                self.max_user_tracks.unwrap_or_default()
+            ),
+            Refusal::QueueFull => format!(
+                "The queue is full, it can only hold {} tracks here!",
+                self.max_queue_length.unwrap_or_default()
+            ),
+        }
+    }
+
+    /// Sums up why a bunch of tracks were refused
+    fn summarize(&self, refusals: &HashMap<Refusal, usize>) -> String {
+        let total = refusals.values().sum::<usize>();
+        let reasons = [
+            (Refusal::TooLong, "too long"),
+            (Refusal::Duplicate, "already queued"),
+            (Refusal::UserFull, "over your track limit"),
+            (Refusal::QueueFull, "past the queue limit"),
+        ]
+        .into_iter()
+        .filter_map(|(refusal, reason)| {
+            refusals
+                .get(&refusal)
+                .map(|count| format!("{count} {reason}"))
+        })
+        .collect::<Vec<_>>();
+        format!("Left out {total} tracks: {}", reasons.join(", "))
+    }
+}
+
+/// Whether the author can queue anything regardless of the limits
+async fn is_exempt(ctx: &Context<'_>, guild_settings: &GuildSettings) -> bool {
+    if permissions::is_dj(ctx).await {
+        return true;
+    }
+    let (Some(guild_id), Some(role)) = (ctx.guild_id(), guild_settings.limits_exempt_role) else {
+        return false;
+    };
+    ctx.author()
+        .has_role(ctx, guild_id, serenity::RoleId::new(role))
+        .await
+        .unwrap_or(false)
+}
+
+/// Drops the tracks that go over the guild's limits, unless the author is exempt from them
+///
+/// Returns the tracks that can be queued, and why the others can't if any were dropped
+pub async fn apply(
+    ctx: &Context<'_>,
+    guild_id: serenity::GuildId,
+    tracks: Vec<(Input, CustomMetadata)>,
+) -> Result<(Vec<(Input, CustomMetadata)>, Option<String>), Error> {
+    let guild_settings = settings::get_guild_settings(ctx.serenity_context(), guild_id).await;
+    if is_exempt(ctx, &guild_settings).await {
+        return Ok((tracks, None));
+    }
+    let limits = Limits::new(&guild_settings);
+
+    let manager = songbird::get(ctx.serenity_context()).await.unwrap().clone();
+    let mut queued = match manager.get(guild_id) {
+        Some(call) => call
+            .lock()
+            .await
+            .queue()
+            .current_queue()
+            .iter()
+            // autoplay only fills in until someone asks for something, so it doesn't take up room
+            .filter(|trackhandle| !trackhandle.data::<CustomMetadata>().autoplay)
+            .map(|trackhandle| {
+                let metadata = trackhandle.data::<CustomMetadata>();
+                (
+                    metadata.aux_metadata.source_url.clone(),
+                    metadata.requested_by.clone(),
+                )
+            })
+            .collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+
+    let mut allowed = Vec::new();
+    let mut refusals = HashMap::new();
+    let mut last_refused = None;
+    for (source, metadata) in tracks {
+        match limits.check(&metadata, &queued) {
+            Ok(()) => {
+                queued.push((
+                    metadata.aux_metadata.source_url.clone(),
+                    metadata.requested_by.clone(),
+                ));
+                allowed.push((source, metadata));
+            }
+            Err(refusal) => {
+                *refusals.entry(refusal).or_insert(0usize) += 1;
+                last_refused = Some((refusal, metadata.aux_metadata.title.clone()));
+            }
+        }
+    }
+
+    let message = match (refusals.values().sum::<usize>(), last_refused) {
+        (1, Some((refusal, title))) => {
+            Some(limits.explain(refusal, title.as_deref().unwrap_or("Untitled")))
+        }
+        (0, _) | (_, None) => None,
+        _ => Some(limits.summarize(&refusals)),
+    };
+
+    Ok((allowed, message))
+}
+
+/// Adds why some tracks were left out to a reply
+pub fn footnote(refused: Option<String>) -> String {
+    refused
+        .map(|refused| format!("\n{refused}"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> Limits {
+        Limits {
+            max_track_length: Some(Duration::from_secs(10 * 60)),
+            max_user_tracks: Some(2),
+            max_queue_length: Some(4),
+            allow_duplicates: false,
+        }
+    }
+
+    fn track(url: &str, requested_by: &str, minutes: u64) -> CustomMetadata {
+        let mut metadata = CustomMetadata {
+            requested_by: requested_by.to_string(),
+            ..Default::default()
+        };
+        metadata.aux_metadata.source_url = Some(url.to_string());
+        metadata.aux_metadata.duration = Some(Duration::from_secs(minutes * 60));
+        metadata
+    }
+
+    fn queued(tracks: &[(&str, &str)]) -> Vec<(Option<String>, String)> {
+        tracks
+            .iter()
+            .map(|(url, requested_by)| (Some(url.to_string()), requested_by.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn allows_tracks_within_the_limits() {
+        let queue = queued(&[("a", "<@1>"), ("b", "<@2>")]);
+        assert_eq!(limits().check(&track("c", "<@1>", 3), &queue), Ok(()));
+    }
+
+    #[test]
+    fn refuses_long_tracks() {
+        assert_eq!(
+            limits().check(&track("a", "<@1>", 11), &[]),
+            Err(Refusal::TooLong)
+        );
+    }
+
+    #[test]
+    fn livestreams_have_no_length_to_refuse() {
+        let mut live = track("a", "<@1>", 0);
+        live.aux_metadata.duration = None;
+        assert_eq!(limits().check(&live, &[]), Ok(()));
+    }
+
+    #[test]
+    fn refuses_duplicates() {
+        let queue = queued(&[("a", "<@2>")]);
+        assert_eq!(
+            limits().check(&track("a", "<@1>", 3), &queue),
+            Err(Refusal::Duplicate)
+        );
+    }
+
+    #[test]
+    fn allows_duplicates_when_turned_on() {
+        let limits = Limits {
+            allow_duplicates: true,
+            ..limits()
+        };
+        let queue = queued(&[("a", "<@2>")]);
+        assert_eq!(limits.check(&track("a", "<@1>", 3), &queue), Ok(()));
+    }
+
+    #[test]
+    fn tracks_without_a_url_are_never_duplicates() {
+        let mut metadata = track("a", "<@1>", 3);
+        metadata.aux_metadata.source_url = None;
+        let queue = vec![(None, "<@2>".to_string())];
+        assert_eq!(limits().check(&metadata, &queue), Ok(()));
+    }
+
+    #[test]
+    fn the_playing_track_counts_toward_the_user_limit() {
+        // the first entry is the track that's playing
+        let queue = queued(&[("a", "<@1>"), ("b", "<@1>")]);
+        assert_eq!(
+            limits().check(&track("c", "<@1>", 3), &queue),
+            Err(Refusal::UserFull)
+        );
+        assert_eq!(limits().check(&track("c", "<@2>", 3), &queue), Ok(()));
+    }
+
+    #[test]
+    fn refuses_past_the_queue_length() {
+        let queue = queued(&[("a", "<@2>"), ("b", "<@3>"), ("c", "<@4>"), ("d", "<@5>")]);
+        assert_eq!(
+            limits().check(&track("e", "<@1>", 3), &queue),
+            Err(Refusal::QueueFull)
+        );
+    }
+
+    #[test]
+    fn no_limits_allow_everything() {
+        let limits = Limits {
+            max_track_length: None,
+            max_user_tracks: None,
+            max_queue_length: None,
+            allow_duplicates: true,
+        };
+        let queue = queued(&[("a", "<@1>"); 50]);
+        assert_eq!(limits.check(&track("a", "<@1>", 600), &queue), Ok(()));
+    }
+
+    #[test]
+    fn duplicates_within_one_batch_are_caught() {
+        // apply() adds every allowed track to `queued` before checking the next one
+        let limits = limits();
+        let mut queue = Vec::new();
+        let first = track("a", "<@1>", 3);
+        assert_eq!(limits.check(&first, &queue), Ok(()));
+        queue.push((
+            first.aux_metadata.source_url.clone(),
+            first.requested_by.clone(),
+        ));
+        assert_eq!(
+            limits.check(&track("a", "<@1>", 3), &queue),
+            Err(Refusal::Duplicate)
+        );
+    }
+
+    #[test]
+    fn summarizes_refusals() {
+        let refusals = HashMap::from([(Refusal::TooLong, 2), (Refusal::QueueFull, 1)]);
+        assert_eq!(
+            limits().summarize(&refusals),
+            "Left out 3 tracks: 2 too long, 1 past the queue limit"
+        );
+    }
+
+    #[test]
+    fn footnote_is_empty_without_refusals() {
+        assert_eq!(footnote(None), "");
+        assert_eq!(footnote(Some("Nope".to_string())), "\nNope");
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::atomic::Ordering;
+
+use poise::serenity_prelude as serenity;
+use songbird::events::{Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent};
+use songbird::tracks::TrackHandle;
+
+use crate::commands::queueops;
+use crate::settings;
+use crate::utils::{self, CustomMetadata, SavedTrack};
+
+/// What a guild's queue repeats
+#[derive(
+    poise::ChoiceParameter,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+pub enum LoopMode {
+    /// Finished tracks go to the back of the queue again
+    #[name = "queue"]
+    Queue,
+    /// Every track repeats until it's skipped
+    #[name = "track"]
+    Track,
+    #[default]
+    #[name = "off"]
+    Off,
+}
+
+impl LoopMode {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            LoopMode::Queue => "Looping the whole queue",
+            LoopMode::Track => "Looping every track until it's skipped",
+            LoopMode::Off => "Stopped looping",
+        }
+    }
+}
+
+/// Puts tracks back at the end of the queue once they end, while the guild loops its queue
+struct QueueLooper {
+    ctx: serenity::Context,
+    guild_id: serenity::GuildId,
+}
+
+impl QueueLooper {
+    async fn requeue(&self, trackhandle: &TrackHandle) -> eyre::Result<()> {
+        let manager = songbird::get(&self.ctx).await.unwrap().clone();
+        // the bot left, so the queue is gone with it
+        let Some(call) = manager.get(self.guild_id) else {
+            return Ok(());
+        };
+        if call.lock().await.current_channel().is_none() {
+            return Ok(());
+        }
+        let Some(saved) = SavedTrack::from_handle(trackhandle) else {
+            return Ok(());
+        };
+
+        let http_client = {
+            let data = self.ctx.data.read().await;
+            data.get::<utils::HttpKey>().cloned().unwrap()
+        };
+        queueops::enqueue(
+            &self.ctx,
+            self.guild_id,
+            vec![saved.into_source(http_client.clone())],
+            false,
+            true,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[serenity::async_trait]
+impl VoiceEventHandler for QueueLooper {
+    async fn act(&self, event: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(tracks) = event else {
+            return None;
+        };
+        if settings::get_guild_settings(&self.ctx, self.guild_id)
+            .await
+            .loop_mode
+            != LoopMode::Queue
+        {
+            return None;
+        }
+
+        for (state, trackhandle) in tracks.iter() {
+            // tracks that were removed before their turn never played
+            if state.play_time.is_zero() {
+                continue;
+            }
+            if trackhandle
+                .data::<CustomMetadata>()
+                .discarded
+                .load(Ordering::Relaxed)
+            {
+                continue;
+            }
+            if let Err(e) = self.requeue(trackhandle).await {
+                log::error!("Can't loop the track back in: {:?}", e);
+            }
+        }
+
+        None
+    }
+}
+
+/// Loops the track back into its guild's queue when it ends, if the guild loops its queue by then
+pub fn add_events(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    trackhandle: &TrackHandle,
+) -> songbird::error::TrackResult<()> {
+    trackhandle.add_event(
+        Event::Track(TrackEvent::End),
+        QueueLooper {
+            ctx: ctx.clone(),
+            guild_id,
+        },
+    )
+}
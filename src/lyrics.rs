@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::prelude::TypeMapKey;
+
+use crate::config::get_config;
+use crate::utils::{CustomMetadata, SourceKind};
+
+/// A track's lyrics, timed if the provider knows when each line is sung
+#[derive(Clone, Debug)]
+pub enum Lyrics {
+    Plain(String),
+    /// Lines in the order they're sung, each with the position in the original audio it starts at
+    Synced(Vec<(Duration, String)>),
+}
+
+impl Lyrics {
+    /// Parses LRC lyrics, falls back to plain text if there are no timestamps in them
+    pub fn from_lrc(text: &str) -> Self {
+        let mut offset = 0i64;
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let mut rest = line.trim();
+            let mut times = Vec::new();
+            while let Some((tag, after)) =
+                rest.strip_prefix('[').and_then(|tag| tag.split_once(']'))
+            {
+                if let Some(value) = tag.strip_prefix("offset:") {
+                    offset = value.trim().parse().unwrap_or(0);
+                } else if let Some(time) = parse_timestamp(tag) {
+                    times.push(time);
+                }
+                rest = after;
+            }
+            lines.extend(
+                times
+                    .into_iter()
+                    .map(|time| (time, rest.trim().to_string())),
+            );
+        }
+
+        if lines.is_empty() {
+            return Lyrics::Plain(text.trim().to_string());
+        }
+        // a positive offset makes the lines show up sooner
+        let shift = Duration::from_millis(offset.unsigned_abs());
+        let mut lines = lines
+            .into_iter()
+            .map(|(time, line)| match offset {
+                0.. => (time.saturating_sub(shift), line),
+                _ => (time.saturating_add(shift), line),
+            })
+            .collect::<Vec<_>>();
+        lines.sort_by_key(|(time, _)| *time);
+        Lyrics::Synced(lines)
+    }
+
+    pub fn text(&self) -> String {
+        match self {
+            Lyrics::Plain(text) => text.clone(),
+            Lyrics::Synced(lines) => lines
+                .iter()
+                .map(|(_, line)| line.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+/// Parses `mm:ss.xx` or `mm:ss`, anything else (like metadata tags) is `None`
+fn parse_timestamp(tag: &str) -> Option<Duration> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let is_number = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+    if !is_number(minutes) || !is_number(seconds) || !(fraction.is_empty() || is_number(fraction)) {
+        return None;
+    }
+
+    let seconds = minutes
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(60)?
+        .checked_add(seconds.parse::<u64>().ok()?)?;
+    // `.5` is half a second and `.05` is 50ms, past milliseconds doesn't matter
+    let millis = format!("{:0<3}", &fraction[..fraction.len().min(3)])
+        .parse::<u64>()
+        .ok()?;
+    Duration::from_secs(seconds).checked_add(Duration::from_millis(millis))
+}
+
+/// What providers have to go by to find a track's lyrics
+#[derive(Clone, Debug, Default)]
+pub struct LyricsQuery {
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<Duration>,
+    /// Where a library track's file is
+    pub path: Option<PathBuf>,
+}
+
+impl LyricsQuery {
+    pub fn new(metadata: &CustomMetadata) -> Self {
+        let aux_metadata = &metadata.aux_metadata;
+        let title = aux_metadata
+            .track
+            .clone()
+            .or(aux_metadata.title.clone())
+            .unwrap_or_default();
+        // youtube's auto-generated music channels are named after the artist
+        let artist = aux_metadata.artist.clone().or(aux_metadata
+            .channel
+            .as_deref()
+            .map(|channel| channel.trim_end_matches(" - Topic").to_string()));
+
+        // uploads are often titled "Artist - Title", which beats the uploader's name
+        let (artist, title) = match (aux_metadata.artist.is_some(), title.split_once(" - ")) {
+            (false, Some((artist, title))) => (Some(artist.trim().to_string()), title.trim()),
+            _ => (artist, title.as_str()),
+        };
+
+        Self {
+            title: title.to_string(),
+            artist,
+            album: aux_metadata.album.clone(),
+            duration: aux_metadata.duration,
+            path: match metadata.kind {
+                SourceKind::File => aux_metadata.source_url.clone().map(PathBuf::from),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Somewhere lyrics can be looked up
+#[serenity::async_trait]
+pub trait LyricsProvider: Send + Sync {
+    /// Returns `None` if it doesn't know the track
+    async fn fetch(&self, query: &LyricsQuery) -> eyre::Result<Option<Lyrics>>;
+}
+
+/// Reads the `.lrc` file next to a library track
+pub struct LrcFileProvider;
+
+#[serenity::async_trait]
+impl LyricsProvider for LrcFileProvider {
+    async fn fetch(&self, query: &LyricsQuery) -> eyre::Result<Option<Lyrics>> {
+        let Some(path) = &query.path else {
+            return Ok(None);
+        };
+        match tokio::fs::read_to_string(path.with_extension("lrc")).await {
+            Ok(text) => Ok(Some(Lyrics::from_lrc(&text))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HttpLyrics {
+    plain_lyrics: Option<String>,
+    synced_lyrics: Option<String>,
+}
+
+/// Asks a server with an LRCLIB compatible API
+pub struct HttpProvider {
+    http_client: reqwest::Client,
+    base_url: String,
+}
+
+impl HttpProvider {
+    pub fn new(http_client: reqwest::Client, base_url: String) -> Self {
+        Self {
+            http_client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[serenity::async_trait]
+impl LyricsProvider for HttpProvider {
+    async fn fetch(&self, query: &LyricsQuery) -> eyre::Result<Option<Lyrics>> {
+        let Some(artist) = &query.artist else {
+            return Ok(None);
+        };
+        let mut params = vec![
+            ("track_name", query.title.clone()),
+            ("artist_name", artist.clone()),
+        ];
+        params.extend(query.album.clone().map(|album| ("album_name", album)));
+        params.extend(
+            query
+                .duration
+                .map(|duration| ("duration", duration.as_secs().to_string())),
+        );
+
+        let response = self
+            .http_client
+            .get(format!("{}/api/get", self.base_url))
+            .query(&params)
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let found =
+            serde_json::from_slice::<HttpLyrics>(&response.error_for_status()?.bytes().await?)?;
+
+        Ok(match (found.synced_lyrics, found.plain_lyrics) {
+            (Some(synced), _) if !synced.trim().is_empty() => Some(Lyrics::from_lrc(&synced)),
+            (_, Some(plain)) if !plain.trim().is_empty() => Some(Lyrics::Plain(plain)),
+            _ => None,
+        })
+    }
+}
+
+/// Goes through the providers in order until one has the lyrics
+pub struct LyricsFinder {
+    providers: Vec<Box<dyn LyricsProvider>>,
+}
+
+impl LyricsFinder {
+    /// Local files first, then the lyrics server unless config.toml turned it off
+    pub fn new(http_client: reqwest::Client) -> Self {
+        let mut providers: Vec<Box<dyn LyricsProvider>> = vec![Box::new(LrcFileProvider)];
+        let url = &get_config().lyrics_url;
+        if !url.is_empty() {
+            providers.push(Box::new(HttpProvider::new(http_client, url.clone())));
+        }
+        Self { providers }
+    }
+
+    pub async fn find(&self, query: &LyricsQuery) -> Option<Lyrics> {
+        for provider in &self.providers {
+            match provider.fetch(query).await {
+                Ok(Some(lyrics)) => return Some(lyrics),
+                Ok(None) => {}
+                Err(e) => log::warn!("Can't fetch lyrics: {:?}", e),
+            }
+        }
+        None
+    }
+}
+
+pub struct LyricsKey;
+
+impl TypeMapKey for LyricsKey {
+    type Value = Arc<LyricsFinder>;
+}
+
+pub async fn get_lyrics_finder(ctx: &serenity::Context) -> Arc<LyricsFinder> {
+    let data = ctx.data.read().await;
+    data.get::<LyricsKey>().cloned().unwrap()
+}
+
+/// The live lyrics following along in each guild, a guild only gets one at a time
+#[derive(Default)]
+pub struct LiveLyrics {
+    next_id: AtomicU64,
+    sessions: std::sync::Mutex<HashMap<serenity::GuildId, u64>>,
+}
+
+impl LiveLyrics {
+    /// Starts following along in the guild, which ends the session that was going there
+    pub fn start(&self, guild_id: serenity::GuildId) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.sessions.lock().unwrap().insert(guild_id, id);
+        id
+    }
+
+    /// Whether the session is still the guild's, rather than ended or replaced by a newer one
+    pub fn is_current(&self, guild_id: serenity::GuildId, id: u64) -> bool {
+        self.sessions.lock().unwrap().get(&guild_id) == Some(&id)
+    }
+
+    pub fn end(&self, guild_id: serenity::GuildId, id: u64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.get(&guild_id) == Some(&id) {
+            sessions.remove(&guild_id);
+        }
+    }
+}
+
+pub struct LiveLyricsKey;
+
+impl TypeMapKey for LiveLyricsKey {
+    type Value = Arc<LiveLyrics>;
+}
+
+pub async fn get_live_lyrics(ctx: &serenity::Context) -> Arc<LiveLyrics> {
+    let data = ctx.data.read().await;
+    data.get::<LiveLyricsKey>().cloned().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synced(text: &str) -> Vec<(Duration, String)> {
+        match Lyrics::from_lrc(text) {
+            Lyrics::Synced(lines) => lines,
+            Lyrics::Plain(text) => panic!("expected timed lyrics, got {text:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_timed_lines() {
+        assert_eq!(
+            synced("[00:01.50]first\n[01:02.05] second "),
+            vec![
+                (Duration::from_millis(1500), "first".to_string()),
+                (Duration::from_millis(62050), "second".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeats_lines_with_several_timestamps() {
+        assert_eq!(
+            synced("[00:10.00][00:01.00]chorus\n[00:05]verse"),
+            vec![
+                (Duration::from_secs(1), "chorus".to_string()),
+                (Duration::from_secs(5), "verse".to_string()),
+                (Duration::from_secs(10), "chorus".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_offsets() {
+        // a positive offset shows lines sooner
+        assert_eq!(
+            synced("[offset:+500]\n[00:02.00]line\n[00:00.20]early"),
+            vec![
+                (Duration::ZERO, "early".to_string()),
+                (Duration::from_millis(1500), "line".to_string()),
+            ]
+        );
+        assert_eq!(
+            synced("[offset:-500]\n[00:02.00]line"),
+            vec![(Duration::from_millis(2500), "line".to_string())]
+        );
+    }
+
+    #[test]
+    fn skips_metadata_tags() {
+        assert_eq!(
+            synced("[ar:Artist]\n[ti:Title]\n[length:03:20]\n[00:01.00]line"),
+            vec![(Duration::from_secs(1), "line".to_string())]
+        );
+    }
+
+    #[test]
+    fn keeps_empty_lines_for_instrumental_parts() {
+        assert_eq!(
+            synced("[00:01.00]\n[00:02.00]line"),
+            vec![
+                (Duration::from_secs(1), String::new()),
+                (Duration::from_secs(2), "line".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_plain_text() {
+        match Lyrics::from_lrc("  just words\nno timestamps  ") {
+            Lyrics::Plain(text) => assert_eq!(text, "just words\nno timestamps"),
+            Lyrics::Synced(lines) => panic!("expected plain lyrics, got {lines:?}"),
+        }
+        match Lyrics::from_lrc("[ar:Artist]\n[ti:Title]") {
+            Lyrics::Plain(_) => {}
+            Lyrics::Synced(lines) => panic!("expected plain lyrics, got {lines:?}"),
+        }
+    }
+
+    #[test]
+    fn ignores_invalid_timestamps() {
+        assert_eq!(
+            synced(
+                "[00:-1]negative\n[00:nan]nan\n[00:inf]inf\n[99999999999999999999:00]huge\n\
+                 [00:1e5]exponent\n[:01]empty\n[00:01.00]line"
+            ),
+            vec![(Duration::from_secs(1), "line".to_string())]
+        );
+    }
+
+    #[test]
+    fn joins_synced_lines_into_text() {
+        assert_eq!(
+            Lyrics::from_lrc("[00:01]one\n[00:02]two").text(),
+            "one\ntwo"
+        );
+    }
+
+    #[test]
+    fn newer_live_lyrics_end_the_older_ones() {
+        let live = LiveLyrics::default();
+        let guild_id = serenity::GuildId::new(1);
+        let first = live.start(guild_id);
+        let second = live.start(guild_id);
+        assert!(!live.is_current(guild_id, first));
+        assert!(live.is_current(guild_id, second));
+
+        // the old one ending doesn't end the new one
+        live.end(guild_id, first);
+        assert!(live.is_current(guild_id, second));
+        live.end(guild_id, second);
+        assert!(!live.is_current(guild_id, second));
+    }
+
+    #[test]
+    fn live_lyrics_are_per_guild() {
+        let live = LiveLyrics::default();
+        let first = live.start(serenity::GuildId::new(1));
+        live.start(serenity::GuildId::new(2));
+        assert!(live.is_current(serenity::GuildId::new(1), first));
+    }
+}
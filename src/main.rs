@@ -1,17 +1,55 @@
+use clap::Parser;
 use poise::serenity_prelude as serenity;
 use songbird::SerenityInit;
 use std::sync::Arc;
 
+mod announcer;
+mod autoplay;
 mod commands;
 mod config;
+mod effects;
+mod grace;
 mod handler;
+mod history;
+mod icy;
+mod idle;
+mod library;
+mod limits;
+mod looping;
+mod lyrics;
+mod permissions;
+mod persistence;
+mod playlists;
+mod reload;
+mod settings;
+mod storage;
+mod suggestions;
 mod utils;
+mod votes;
+mod ytdl;
 
 pub struct Data {} // User data, which is stored and accessible in all command invocations
 
 pub type Error = eyre::Report;
 pub type Context<'a> = poise::Context<'a, Data, Error>;
 
+/// a (mostly) pure-rust discord music bot
+///
+/// Any config field can also be set with a MUSICALCAT_<FIELD> environment variable, like MUSICALCAT_DISCORD_TOKEN
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Config file to read, it's fine if it doesn't exist when the environment sets everything
+    #[arg(short, long, default_value = "config.toml")]
+    config: std::path::PathBuf,
+    /// Least important messages to log: off, error, warn, info, debug or trace
+    #[arg(short, long, default_value = "warn")]
+    log_level: log::LevelFilter,
+    /// Check the config and exit without connecting to discord
+    #[arg(long)]
+    dry_run: bool,
+}
+
 /// Register/Unregister slash commands (botowner only)
 #[poise::command(slash_command)]
 pub async fn register(ctx: Context<'_>) -> Result<(), Error> {
@@ -21,9 +59,17 @@ pub async fn register(ctx: Context<'_>) -> Result<(), Error> {
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
-    simplelog::SimpleLogger::init(log::LevelFilter::Warn, simplelog::Config::default())?;
-    let config_path = std::path::Path::new(".").join("config.toml");
-    let config = config::init_config(&config_path).await?;
+    let args = Args::parse();
+    simplelog::SimpleLogger::init(args.log_level, simplelog::Config::default())?;
+
+    if args.dry_run {
+        config::read_config(&args.config)
+            .await?
+            .warn_presence_problems();
+        println!("The config is valid!");
+        return Ok(());
+    }
+    let config = config::init_config(&args.config).await?;
 
     let intents = serenity::GatewayIntents::GUILD_VOICE_STATES | serenity::GatewayIntents::GUILDS;
 
@@ -33,17 +79,34 @@ async fn main() -> eyre::Result<()> {
                 register(),
                 commands::queueops::play_test(),
                 commands::queueops::play(),
+                commands::search::search(),
                 commands::queueops::stop(),
                 commands::queueops::next(),
                 commands::queueops::shuffle(),
                 commands::queueops::clear(),
                 commands::queueops::remove(),
+                commands::queueops::move_track(),
+                commands::queueops::swap(),
+                commands::queueops::skipto(),
+                commands::trackops::loop_mode(),
                 commands::trackops::loop_current(),
                 commands::trackops::stop_looping(),
                 commands::trackops::resume(),
                 commands::trackops::pause(),
+                commands::trackops::replay(),
+                commands::trackops::seek(),
+                commands::trackops::volume(),
                 commands::queue::current(),
                 commands::queue::queue(),
+                commands::lyrics::lyrics(),
+                commands::history::history(),
+                commands::history::previous(),
+                commands::settings::settings(),
+                commands::permissions::permissions(),
+                commands::library::library(),
+                commands::playlist::playlist(),
+                commands::filter::filter(),
+                commands::admin::admin(),
             ],
             owners: config
                 .owners
@@ -54,17 +117,51 @@ async fn main() -> eyre::Result<()> {
             ..Default::default()
         },
         shard_manager: std::sync::Mutex::new(None),
+        queues_restored: std::sync::atomic::AtomicBool::new(false),
     };
     poise::set_qualified_names(&mut handler.options.commands); // some setup
 
+    let settings_store = Arc::new(settings::SettingsStore::load().await?);
+
     let player = songbird::Songbird::serenity();
     let handler = Arc::new(handler);
+    let http_client = reqwest::Client::new();
+    let lyrics_finder = Arc::new(lyrics::LyricsFinder::new(http_client.clone()));
     let mut client = serenity::Client::builder(&config.discord_token, intents)
         .event_handler_arc(handler.clone())
         .register_songbird_with(player.clone())
-        .type_map_insert::<utils::HttpKey>(reqwest::Client::new())
+        .type_map_insert::<utils::HttpKey>(http_client)
+        .type_map_insert::<settings::SettingsKey>(settings_store)
+        .type_map_insert::<suggestions::SuggestionsKey>(Default::default())
+        .type_map_insert::<announcer::AnnouncerKey>(Default::default())
+        .type_map_insert::<votes::SkipVotesKey>(Default::default())
+        .type_map_insert::<idle::IdleTimersKey>(Default::default())
+        .type_map_insert::<grace::GracePeriodsKey>(Default::default())
+        .type_map_insert::<lyrics::LyricsKey>(lyrics_finder)
+        .type_map_insert::<lyrics::LiveLyricsKey>(Default::default())
         .await?;
-    *handler.shard_manager.lock().unwrap() = Some(client.shard_manager.clone());
+
+    if let Some(library_dir) = &config.library_dir {
+        let library = Arc::new(library::Library::new(library_dir.clone()));
+        library.start()?;
+        client
+            .data
+            .write()
+            .await
+            .insert::<library::LibraryKey>(library);
+    }
+
+    let shard_manager = client.shard_manager.clone();
+    *handler.shard_manager.lock().unwrap() = Some(shard_manager.clone());
+
+    // the watcher stops once it's dropped, so it's kept until the bot stops
+    let _config_watcher = reload::watch(shard_manager.clone())?;
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        if let Err(e) = reload::reload_on_sighup(shard_manager).await {
+            log::error!("{:?}", e)
+        }
+    });
 
     client.start().await?;
 
@@ -0,0 +1,185 @@
+use poise::serenity_prelude as serenity;
+use songbird::tracks::TrackHandle;
+
+use crate::settings;
+use crate::utils;
+use crate::Context;
+use crate::Error;
+
+/// Who can use a command, every level also lets the levels above it through
+#[derive(
+    poise::ChoiceParameter,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+pub enum PermissionLevel {
+    #[name = "everyone"]
+    Everyone,
+    /// Whoever requested the current track, or the tracks being removed for /remove
+    #[name = "requester"]
+    Requester,
+    #[name = "dj"]
+    Dj,
+    /// Bot owners and people who can manage the server
+    #[name = "owner"]
+    Owner,
+}
+
+/// Commands the permission layer covers, and the level they need once a server has a DJ role
+pub const COMMANDS: &[(&str, PermissionLevel)] = &[
+    ("play", PermissionLevel::Everyone),
+    ("play_test", PermissionLevel::Owner),
+    ("stop", PermissionLevel::Dj),
+    ("next", PermissionLevel::Requester),
+    ("shuffle", PermissionLevel::Dj),
+    ("clear", PermissionLevel::Dj),
+    ("remove", PermissionLevel::Requester),
+    ("previous", PermissionLevel::Requester),
+    ("move", PermissionLevel::Dj),
+    ("swap", PermissionLevel::Dj),
+    ("skipto", PermissionLevel::Dj),
+    ("resume", PermissionLevel::Requester),
+    ("pause", PermissionLevel::Requester),
+    ("replay", PermissionLevel::Requester),
+    ("seek", PermissionLevel::Requester),
+    ("loop", PermissionLevel::Dj),
+    ("loop_current", PermissionLevel::Requester),
+    ("stop_looping", PermissionLevel::Requester),
+    ("volume", PermissionLevel::Dj),
+    ("search", PermissionLevel::Everyone),
+    ("library play", PermissionLevel::Everyone),
+    ("library album", PermissionLevel::Everyone),
+    ("library artist", PermissionLevel::Everyone),
+    ("playlist load", PermissionLevel::Everyone),
+    ("filter set", PermissionLevel::Dj),
+    ("filter clear", PermissionLevel::Dj),
+    ("lyrics", PermissionLevel::Everyone),
+];
+
+/// Commands that queue tracks some other way than /play, they need what /play does unless given their own level
+const QUEUE_COMMANDS: &[&str] = &[
+    "search",
+    "library play",
+    "library album",
+    "library artist",
+    "playlist load",
+];
+
+pub fn is_known_command(command: &str) -> bool {
+    COMMANDS.iter().any(|(name, _)| *name == command)
+}
+
+/// The level `command` needs in this guild
+///
+/// Without a DJ role the bot stays a free-for-all, unless a command was given a level by hand
+pub fn required_level(guild_settings: &settings::GuildSettings, command: &str) -> PermissionLevel {
+    if let Some(level) = guild_settings.permissions.get(command) {
+        return *level;
+    }
+    if QUEUE_COMMANDS.contains(&command) {
+        return required_level(guild_settings, "play");
+    }
+    if guild_settings.dj_role.is_none() {
+        return PermissionLevel::Everyone;
+    }
+    // with vote skipping on, /next only starts a vote for people who can't skip right away
+    if command == "next" && guild_settings.vote_skip() {
+        return PermissionLevel::Everyone;
+    }
+    COMMANDS
+        .iter()
+        .find(|(name, _)| *name == command)
+        .map(|(_, level)| *level)
+        .unwrap_or(PermissionLevel::Everyone)
+}
+
+/// Whether the author has the server's DJ role, or is an owner
+pub async fn is_dj(ctx: &Context<'_>) -> bool {
+    if utils::can_manage_guild(ctx).await {
+        return true;
+    }
+    let Some(guild_id) = ctx.guild_id() else {
+        return false;
+    };
+    let Some(dj_role) = settings::get_guild_settings(ctx.serenity_context(), guild_id)
+        .await
+        .dj_role
+    else {
+        return false;
+    };
+    ctx.author()
+        .has_role(ctx, guild_id, serenity::RoleId::new(dj_role))
+        .await
+        .unwrap_or(false)
+}
+
+/// Whether the author requested the track that's playing, also true when nothing is or nobody
+/// did because autoplay picked it
+pub async fn is_requester(ctx: &Context<'_>) -> bool {
+    let Some(guild_id) = ctx.guild_id() else {
+        return false;
+    };
+    let manager = songbird::get(ctx.serenity_context()).await.unwrap().clone();
+    let Some(call) = manager.get(guild_id) else {
+        return true;
+    };
+    let Some(trackhandle) = call.lock().await.queue().current() else {
+        return true;
+    };
+    let metadata = trackhandle.data::<utils::CustomMetadata>();
+    metadata.autoplay || metadata.requested_by == format!("<@{}>", ctx.author().id)
+}
+
+/// Whether the author requested every one of `tracks`, or is a DJ, anyone can remove what autoplay picked
+pub async fn can_remove(ctx: &Context<'_>, tracks: &[TrackHandle]) -> bool {
+    let author = format!("<@{}>", ctx.author().id);
+    tracks.iter().all(|trackhandle| {
+        let metadata = trackhandle.data::<utils::CustomMetadata>();
+        metadata.autoplay || metadata.requested_by == author
+    }) || is_dj(ctx).await
+}
+
+async fn has_level(ctx: &Context<'_>, command: &str, level: PermissionLevel) -> bool {
+    match level {
+        PermissionLevel::Everyone => true,
+        // /remove checks the tracks it's given itself
+        PermissionLevel::Requester if command == "remove" => true,
+        PermissionLevel::Requester => is_requester(ctx).await || is_dj(ctx).await,
+        PermissionLevel::Dj => is_dj(ctx).await,
+        PermissionLevel::Owner => utils::can_manage_guild(ctx).await,
+    }
+}
+
+/// Checks the command against the server's permission levels, telling the author if they can't use it
+pub async fn check(ctx: Context<'_>) -> Result<bool, Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(true);
+    };
+    let command = ctx.command().qualified_name.as_str();
+    let guild_settings = settings::get_guild_settings(ctx.serenity_context(), guild_id).await;
+    let level = required_level(&guild_settings, command);
+
+    if has_level(&ctx, command, level).await {
+        return Ok(true);
+    }
+
+    ctx.say(match level {
+        PermissionLevel::Everyone | PermissionLevel::Requester => {
+            format!("Only whoever requested this track or a DJ can use `/{command}`!")
+        }
+        PermissionLevel::Dj => format!("Only DJs can use `/{command}`!"),
+        PermissionLevel::Owner => {
+            format!("You need the Manage Server permission to use `/{command}`!")
+        }
+    })
+    .await?;
+
+    Ok(false)
+}
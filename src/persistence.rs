@@ -0,0 +1,333 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use poise::serenity_prelude as serenity;
+use songbird::tracks::LoopState;
+
+use crate::announcer;
+use crate::commands::queueops;
+use crate::effects;
+use crate::grace;
+use crate::storage;
+use crate::utils;
+use crate::utils::SavedTrack;
+
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+pub enum SavedLoopState {
+    Infinite,
+    Finite(u32),
+}
+
+impl From<LoopState> for SavedLoopState {
+    fn from(value: LoopState) -> Self {
+        match value {
+            LoopState::Infinite => Self::Infinite,
+            LoopState::Finite(num) => Self::Finite(num.get()),
+        }
+    }
+}
+
+impl From<SavedLoopState> for LoopState {
+    fn from(value: SavedLoopState) -> Self {
+        match value {
+            SavedLoopState::Infinite => Self::Infinite,
+            SavedLoopState::Finite(num) => {
+                Self::Finite(nonmax::NonMaxU32::new(num).unwrap_or(nonmax::NonMaxU32::ZERO))
+            }
+        }
+    }
+}
+
+/// Snapshot of a guild's queue, the first track is the one that was playing
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct SavedQueue {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    /// Position in the original audio, so it still fits if the guild's filters changed
+    pub position: Duration,
+    pub loops: SavedLoopState,
+    pub tracks: Vec<SavedTrack>,
+    /// Where the queue's announcements went
+    #[serde(default)]
+    pub text_channel_id: Option<u64>,
+}
+
+fn queues_dir() -> PathBuf {
+    storage::data_path("queues")
+}
+
+fn queue_path(guild_id: serenity::GuildId) -> PathBuf {
+    queues_dir().join(format!("{guild_id}.json"))
+}
+
+/// Rejoins the saved voice channels and queues up their tracks again
+pub async fn restore_queues(ctx: &serenity::Context) -> eyre::Result<()> {
+    let mut entries = match tokio::fs::read_dir(queues_dir()).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let http_client = {
+        let data = ctx.data.read().await;
+        data.get::<utils::HttpKey>().cloned().unwrap()
+    };
+    let manager = songbird::get(ctx).await.unwrap().clone();
+
+    while let Some(entry) = entries.next_entry().await? {
+        let saved = match storage::load::<SavedQueue>(&entry.path()).await {
+            Ok(Some(saved)) => saved,
+            Ok(None) => continue,
+            Err(e) => {
+                log::error!("Can't read saved queue {:?}: {:?}", entry.path(), e);
+                continue;
+            }
+        };
+        if let Err(e) = restore_queue(ctx, &manager, &http_client, saved).await {
+            log::error!("{:?}", e)
+        }
+    }
+
+    Ok(())
+}
+
+async fn restore_queue(
+    ctx: &serenity::Context,
+    manager: &songbird::Songbird,
+    http_client: &reqwest::Client,
+    saved: SavedQueue,
+) -> eyre::Result<()> {
+    if saved.tracks.is_empty() {
+        return Ok(());
+    }
+
+    let guild_id = serenity::GuildId::new(saved.guild_id);
+    manager
+        .join(guild_id, serenity::ChannelId::new(saved.channel_id))
+        .await?;
+
+    requeue(ctx, http_client, guild_id, saved).await?;
+
+    // nobody is around to leave, so nothing else notices the channel is empty
+    if utils::listeners(ctx, guild_id)
+        .await?
+        .is_some_and(|listeners| listeners.is_empty())
+    {
+        grace::channel_emptied(ctx, guild_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Queues up the saved tracks in the guild's call, picking the first one up where it was
+async fn requeue(
+    ctx: &serenity::Context,
+    http_client: &reqwest::Client,
+    guild_id: serenity::GuildId,
+    saved: SavedQueue,
+) -> eyre::Result<usize> {
+    // whoever resumes it is asking from somewhere, that wins over where it used to announce
+    let announcer = announcer::get_announcer(ctx).await;
+    if let (None, Some(text_channel_id)) = (announcer.channel(guild_id), saved.text_channel_id) {
+        announcer.set_channel(guild_id, serenity::ChannelId::new(text_channel_id));
+    }
+
+    let tracks = saved
+        .tracks
+        .into_iter()
+        .map(|saved_track| saved_track.into_source(http_client.clone()))
+        .collect();
+    let handles = queueops::enqueue(ctx, guild_id, tracks, false, true).await?;
+
+    if let Some(trackhandle) = handles.first() {
+        match LoopState::from(saved.loops) {
+            LoopState::Infinite => trackhandle.enable_loop()?,
+            LoopState::Finite(nonmax::NonMaxU32::ZERO) => {}
+            LoopState::Finite(num) => trackhandle.loop_for(num)?,
+        }
+        let metadata = trackhandle.data::<utils::CustomMetadata>();
+        if !saved.position.is_zero() && !metadata.live {
+            // the track isn't made playable yet, so don't wait on it
+            drop(trackhandle.seek(effects::track_position(&metadata, saved.position)));
+        }
+    }
+
+    Ok(handles.len())
+}
+
+/// Kept queues older than this are dropped, nobody's coming back for them anymore
+const KEPT_QUEUE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A queue kept when the bot left, waiting for /resume
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct KeptQueue {
+    kept_at: SystemTime,
+    queue: SavedQueue,
+}
+
+fn kept_queue_path(guild_id: serenity::GuildId) -> PathBuf {
+    storage::data_path("kept_queues").join(format!("{guild_id}.json"))
+}
+
+/// Saves the guild's queue before the bot leaves, so /resume can pick it back up later
+///
+/// Returns whether there was anything to keep
+pub async fn keep_queue(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    call: &tokio::sync::Mutex<songbird::Call>,
+) -> eyre::Result<bool> {
+    let text_channel = announcer::get_announcer(ctx).await.channel(guild_id);
+    let Some(queue) = snapshot_queue(guild_id, call, text_channel).await? else {
+        return Ok(false);
+    };
+    if queue.tracks.is_empty() {
+        return Ok(false);
+    }
+    let kept = KeptQueue {
+        kept_at: SystemTime::now(),
+        queue,
+    };
+    storage::save(&kept_queue_path(guild_id), &kept).await?;
+    Ok(true)
+}
+
+/// Takes the guild's kept queue off disk, `None` if there isn't one or it's too old to bring back
+pub async fn take_kept_queue(guild_id: serenity::GuildId) -> eyre::Result<Option<KeptQueue>> {
+    let path = kept_queue_path(guild_id);
+    let Some(kept) = storage::load::<KeptQueue>(&path).await? else {
+        return Ok(None);
+    };
+    storage::remove(&path).await?;
+
+    let age = kept.kept_at.elapsed().unwrap_or_default();
+    Ok((age < KEPT_QUEUE_MAX_AGE).then_some(kept))
+}
+
+/// Puts a kept queue back for later, when it couldn't be restored after all
+pub async fn put_back_kept_queue(
+    guild_id: serenity::GuildId,
+    kept: &KeptQueue,
+) -> eyre::Result<()> {
+    storage::save(&kept_queue_path(guild_id), kept).await
+}
+
+/// Drops the guild's kept queue, a new session started so it won't be resumed
+pub async fn drop_kept_queue(guild_id: serenity::GuildId) -> eyre::Result<()> {
+    storage::remove(&kept_queue_path(guild_id)).await
+}
+
+/// Queues up a kept queue in the call the bot is in, returns how many tracks it had
+pub async fn restore_kept_queue(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+    kept: &KeptQueue,
+) -> eyre::Result<usize> {
+    let http_client = {
+        let data = ctx.data.read().await;
+        data.get::<utils::HttpKey>().cloned().unwrap()
+    };
+    requeue(ctx, &http_client, guild_id, kept.queue.clone()).await
+}
+
+/// Periodically writes every guild's queue to disk, and removes the ones that aren't playing anymore
+pub async fn snapshot_loop(ctx: serenity::Context) {
+    let manager = songbird::get(&ctx).await.unwrap().clone();
+    let announcer = announcer::get_announcer(&ctx).await;
+    let mut interval = tokio::time::interval(SNAPSHOT_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = snapshot_queues(&manager, &announcer).await {
+            log::error!("{:?}", e)
+        }
+    }
+}
+
+async fn snapshot_queues(
+    manager: &songbird::Songbird,
+    announcer: &announcer::Announcer,
+) -> eyre::Result<()> {
+    let calls = manager.iter().collect::<Vec<_>>();
+    let mut saved_guilds = HashSet::new();
+
+    for (guild_id, call) in calls {
+        let guild_id = serenity::GuildId::new(guild_id.0.get());
+        let text_channel = announcer.channel(guild_id);
+        let saved = match snapshot_queue(guild_id, &call, text_channel).await {
+            Ok(Some(saved)) => saved,
+            Ok(None) => continue,
+            Err(e) => {
+                log::error!("Can't snapshot the queue of {guild_id}: {:?}", e);
+                // the last snapshot is better than none
+                saved_guilds.insert(guild_id);
+                continue;
+            }
+        };
+        if let Err(e) = storage::save(&queue_path(guild_id), &saved).await {
+            log::error!("Can't save the queue of {guild_id}: {:?}", e);
+        }
+        saved_guilds.insert(guild_id);
+    }
+
+    let mut entries = match tokio::fs::read_dir(queues_dir()).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let Some(guild_id) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        if !saved_guilds.contains(&serenity::GuildId::new(guild_id)) {
+            if let Err(e) = storage::remove(&path).await {
+                log::error!("Can't remove the saved queue {:?}: {:?}", path, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn snapshot_queue(
+    guild_id: serenity::GuildId,
+    call: &tokio::sync::Mutex<songbird::Call>,
+    text_channel: Option<serenity::ChannelId>,
+) -> eyre::Result<Option<SavedQueue>> {
+    let (channel, queue) = {
+        let handler = call.lock().await;
+        (handler.current_channel(), handler.queue().current_queue())
+    };
+
+    let Some(channel) = channel else {
+        return Ok(None);
+    };
+    let Some(current) = queue.first() else {
+        return Ok(None);
+    };
+
+    let (position, loops) = match current.get_info().await {
+        Ok(info) => (
+            effects::source_position(&current.data::<utils::CustomMetadata>(), info.position),
+            info.loops.into(),
+        ),
+        // the track just ended, the next snapshot will pick the new one up
+        Err(_) => (Duration::ZERO, SavedLoopState::Finite(0)),
+    };
+
+    Ok(Some(SavedQueue {
+        guild_id: guild_id.get(),
+        channel_id: channel.0.get(),
+        position,
+        loops,
+        tracks: queue.iter().filter_map(SavedTrack::from_handle).collect(),
+        text_channel_id: text_channel.map(|channel_id| channel_id.get()),
+    }))
+}
@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use poise::serenity_prelude as serenity;
+
+use crate::storage;
+use crate::utils::SavedTrack;
+
+/// `/play` queues the saved playlist called whatever comes after this, autocomplete suggests it
+pub const QUERY_PREFIX: &str = "playlist:";
+
+// playlists are only touched by commands, so a single lock for every file is plenty
+static PLAYLISTS_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[derive(poise::ChoiceParameter, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaylistScope {
+    #[name = "personal"]
+    Personal,
+    #[name = "server"]
+    Server,
+}
+
+/// Whose playlists these are, every owner gets its own file
+#[derive(Clone, Copy, Debug)]
+pub enum PlaylistOwner {
+    User(serenity::UserId),
+    Guild(serenity::GuildId),
+}
+
+impl PlaylistOwner {
+    fn path(&self) -> PathBuf {
+        match self {
+            PlaylistOwner::User(user_id) => {
+                storage::data_path("playlists/users").join(format!("{user_id}.json"))
+            }
+            PlaylistOwner::Guild(guild_id) => {
+                storage::data_path("playlists/guilds").join(format!("{guild_id}.json"))
+            }
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Playlist {
+    pub name: String,
+    /// User who created the playlist
+    pub created_by: u64,
+    pub tracks: Vec<SavedTrack>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+pub struct Playlists {
+    /// Keyed by lowercased name, so names are case insensitive
+    pub playlists: BTreeMap<String, Playlist>,
+}
+
+impl Playlists {
+    pub fn get(&self, name: &str) -> Option<&Playlist> {
+        self.playlists.get(&name.to_lowercase())
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Playlist> {
+        self.playlists.get_mut(&name.to_lowercase())
+    }
+
+    pub fn insert(&mut self, playlist: Playlist) {
+        self.playlists
+            .insert(playlist.name.to_lowercase(), playlist);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Playlist> {
+        self.playlists.remove(&name.to_lowercase())
+    }
+}
+
+pub async fn load(owner: PlaylistOwner) -> eyre::Result<Playlists> {
+    let _guard = PLAYLISTS_LOCK.lock().await;
+    Ok(storage::load(&owner.path()).await?.unwrap_or_default())
+}
+
+/// Modifies the owner's playlists and writes them back to disk
+pub async fn modify<F, O>(owner: PlaylistOwner, func: F) -> eyre::Result<O>
+where
+    F: FnOnce(&mut Playlists) -> O,
+{
+    let _guard = PLAYLISTS_LOCK.lock().await;
+    let path = owner.path();
+    let mut playlists = storage::load(&path).await?.unwrap_or_default();
+    let output = func(&mut playlists);
+    storage::save(&path, &playlists).await?;
+    Ok(output)
+}
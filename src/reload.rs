@@ -0,0 +1,69 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{DebounceEventResult, Debouncer};
+use poise::serenity_prelude as serenity;
+
+use crate::config;
+use crate::handler;
+
+/// Reads config.toml again and applies it, the running config stays if the new one is invalid
+pub async fn reload(shard_manager: &serenity::ShardManager) -> eyre::Result<()> {
+    config::reload_config().await?;
+    handler::apply_presence(shard_manager).await;
+    log::info!("Reloaded {}", config::config_path().display());
+    Ok(())
+}
+
+async fn reload_logged(shard_manager: &serenity::ShardManager) {
+    if let Err(e) = reload(shard_manager).await {
+        log::error!("Can't reload the config: {:?}", e);
+    }
+}
+
+/// Reloads the config whenever its file changes, for as long as the returned watcher is kept around
+pub fn watch(
+    shard_manager: Arc<serenity::ShardManager>,
+) -> eyre::Result<Debouncer<RecommendedWatcher>> {
+    let path = config::config_path();
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    // editors often save by replacing the file, which a watch on the file itself would miss
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    let runtime = tokio::runtime::Handle::current();
+    let mut debouncer = notify_debouncer_mini::new_debouncer(
+        Duration::from_secs(2),
+        move |result: DebounceEventResult| match result {
+            Ok(events) => {
+                if events
+                    .iter()
+                    .any(|event| event.path.file_name() == file_name.as_deref())
+                {
+                    let shard_manager = shard_manager.clone();
+                    runtime.spawn(async move { reload_logged(&shard_manager).await });
+                }
+            }
+            Err(e) => log::error!("{:?}", e),
+        },
+    )?;
+    debouncer
+        .watcher()
+        .watch(dir, RecursiveMode::NonRecursive)?;
+
+    Ok(debouncer)
+}
+
+/// Reloads the config every time the process gets a SIGHUP
+#[cfg(unix)]
+pub async fn reload_on_sighup(shard_manager: Arc<serenity::ShardManager>) -> eyre::Result<()> {
+    let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+    while hangups.recv().await.is_some() {
+        reload_logged(&shard_manager).await;
+    }
+    Ok(())
+}
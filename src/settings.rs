@@ -0,0 +1,513 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::prelude::TypeMapKey;
+use tokio::sync::RwLock;
+
+use crate::config::get_config;
+use crate::effects::Filter;
+use crate::looping::LoopMode;
+use crate::permissions::PermissionLevel;
+use crate::storage;
+
+pub const MAX_VOLUME: u16 = 200;
+
+/// Per-guild overrides, anything left unset falls back to config.toml
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct GuildSettings {
+    pub leave_on_empty: Option<bool>,
+    pub empty_grace_seconds: Option<u32>,
+    pub keep_queue_on_leave: Option<bool>,
+    pub volume: Option<u16>,
+    pub announce_tracks: Option<bool>,
+    pub dj_role: Option<u64>,
+    pub vote_skip: Option<bool>,
+    pub vote_skip_percent: Option<u8>,
+    pub fair_queue: Option<bool>,
+    pub autoplay: Option<bool>,
+    pub idle_timeout_minutes: Option<u32>,
+    pub max_track_minutes: Option<u32>,
+    pub max_user_tracks: Option<usize>,
+    pub max_queue_length: Option<usize>,
+    pub allow_duplicates: Option<bool>,
+    /// Role that doesn't have to stick to the limits above, DJs never do
+    pub limits_exempt_role: Option<u64>,
+    /// Permission levels set by hand, keyed by command name
+    pub permissions: BTreeMap<String, PermissionLevel>,
+    /// Effects applied to every track, managed with /filter
+    pub filters: Vec<Filter>,
+    /// Managed with /loop
+    pub loop_mode: LoopMode,
+}
+
+impl GuildSettings {
+    pub fn leave_on_empty(&self) -> bool {
+        self.leave_on_empty
+            .unwrap_or(get_config().bot_leave_on_empty)
+    }
+
+    pub fn empty_grace_period(&self) -> Option<std::time::Duration> {
+        match self
+            .empty_grace_seconds
+            .unwrap_or(get_config().empty_grace_seconds)
+        {
+            0 => None,
+            seconds => Some(std::time::Duration::from_secs(seconds as u64)),
+        }
+    }
+
+    pub fn keep_queue_on_leave(&self) -> bool {
+        self.keep_queue_on_leave
+            .unwrap_or(get_config().keep_queue_on_leave)
+    }
+
+    pub fn announce_tracks(&self) -> bool {
+        self.announce_tracks.unwrap_or(get_config().announce_tracks)
+    }
+
+    pub fn vote_skip(&self) -> bool {
+        self.vote_skip.unwrap_or(get_config().vote_skip)
+    }
+
+    /// In percent, from 1 to 100
+    pub fn vote_skip_percent(&self) -> u8 {
+        self.vote_skip_percent
+            .unwrap_or(get_config().vote_skip_percent)
+            .clamp(1, 100)
+    }
+
+    pub fn fair_queue(&self) -> bool {
+        self.fair_queue.unwrap_or(get_config().fair_queue)
+    }
+
+    pub fn autoplay(&self) -> bool {
+        self.autoplay.unwrap_or(get_config().autoplay)
+    }
+
+    pub fn idle_timeout(&self) -> Option<std::time::Duration> {
+        match self
+            .idle_timeout_minutes
+            .unwrap_or(get_config().idle_timeout_minutes)
+        {
+            0 => None,
+            minutes => Some(std::time::Duration::from_secs(minutes as u64 * 60)),
+        }
+    }
+
+    pub fn max_track_length(&self) -> Option<std::time::Duration> {
+        match self
+            .max_track_minutes
+            .unwrap_or(get_config().max_track_minutes)
+        {
+            0 => None,
+            minutes => Some(std::time::Duration::from_secs(minutes as u64 * 60)),
+        }
+    }
+
+    pub fn max_user_tracks(&self) -> Option<usize> {
+        Some(self.max_user_tracks.unwrap_or(get_config().max_user_tracks)).filter(|max| *max > 0)
+    }
+
+    pub fn max_queue_length(&self) -> Option<usize> {
+        Some(
+            self.max_queue_length
+                .unwrap_or(get_config().max_queue_length),
+        )
+        .filter(|max| *max > 0)
+    }
+
+    pub fn allow_duplicates(&self) -> bool {
+        self.allow_duplicates
+            .unwrap_or(get_config().allow_duplicates)
+    }
+
+    /// In percent, from 0 to 200
+    pub fn volume(&self) -> u16 {
+        self.volume.unwrap_or(get_config().default_volume)
+    }
+}
+
+#[derive(poise::ChoiceParameter, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    #[name = "leave_on_empty"]
+    LeaveOnEmpty,
+    #[name = "empty_grace_seconds"]
+    EmptyGraceSeconds,
+    #[name = "keep_queue_on_leave"]
+    KeepQueueOnLeave,
+    #[name = "volume"]
+    Volume,
+    #[name = "announce_tracks"]
+    AnnounceTracks,
+    #[name = "dj_role"]
+    DjRole,
+    #[name = "vote_skip"]
+    VoteSkip,
+    #[name = "vote_skip_percent"]
+    VoteSkipPercent,
+    #[name = "fair_queue"]
+    FairQueue,
+    #[name = "autoplay"]
+    Autoplay,
+    #[name = "idle_timeout_minutes"]
+    IdleTimeoutMinutes,
+    #[name = "max_track_minutes"]
+    MaxTrackMinutes,
+    #[name = "max_user_tracks"]
+    MaxUserTracks,
+    #[name = "max_queue_length"]
+    MaxQueueLength,
+    #[name = "allow_duplicates"]
+    AllowDuplicates,
+    #[name = "limits_exempt_role"]
+    LimitsExemptRole,
+}
+
+impl Setting {
+    pub fn all() -> &'static [Setting] {
+        &[
+            Setting::LeaveOnEmpty,
+            Setting::EmptyGraceSeconds,
+            Setting::KeepQueueOnLeave,
+            Setting::Volume,
+            Setting::AnnounceTracks,
+            Setting::DjRole,
+            Setting::VoteSkip,
+            Setting::VoteSkipPercent,
+            Setting::FairQueue,
+            Setting::Autoplay,
+            Setting::IdleTimeoutMinutes,
+            Setting::MaxTrackMinutes,
+            Setting::MaxUserTracks,
+            Setting::MaxQueueLength,
+            Setting::AllowDuplicates,
+            Setting::LimitsExemptRole,
+        ]
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Setting::LeaveOnEmpty => "Leave the voice channel when nobody is listening",
+            Setting::EmptyGraceSeconds => {
+                "Seconds to wait paused for someone to come back before leaving, 0 to leave right away"
+            }
+            Setting::KeepQueueOnLeave => {
+                "Save the queue when leaving an empty channel, so /resume can pick it back up"
+            }
+            Setting::Volume => "Volume new tracks start at, in percent",
+            Setting::AnnounceTracks => "Post a message when a new track starts playing",
+            Setting::DjRole => "Role that can control the queue, setting one turns on /permissions",
+            Setting::VoteSkip => "Make /next start a vote for people who aren't DJs",
+            Setting::VoteSkipPercent => {
+                "Share of the listeners that has to vote to skip, in percent"
+            }
+            Setting::FairQueue => {
+                "Let requesters take turns instead of queueing tracks at the back"
+            }
+            Setting::Autoplay => "Keep playing related tracks once the queue runs out",
+            Setting::IdleTimeoutMinutes => {
+                "Minutes to wait before leaving once nothing is playing, 0 to stay"
+            }
+            Setting::MaxTrackMinutes => "Longest track people can queue in minutes, 0 for no limit",
+            Setting::MaxUserTracks => {
+                "Most tracks one person can have in the queue, 0 for no limit"
+            }
+            Setting::MaxQueueLength => "Most tracks the queue can hold, 0 for no limit",
+            Setting::AllowDuplicates => "Let people queue tracks that are already in the queue",
+            Setting::LimitsExemptRole => "Role that can queue past the limits, DJs always can",
+        }
+    }
+
+    pub fn is_default(&self, settings: &GuildSettings) -> bool {
+        match self {
+            Setting::LeaveOnEmpty => settings.leave_on_empty.is_none(),
+            Setting::EmptyGraceSeconds => settings.empty_grace_seconds.is_none(),
+            Setting::KeepQueueOnLeave => settings.keep_queue_on_leave.is_none(),
+            Setting::Volume => settings.volume.is_none(),
+            Setting::AnnounceTracks => settings.announce_tracks.is_none(),
+            Setting::DjRole => settings.dj_role.is_none(),
+            Setting::VoteSkip => settings.vote_skip.is_none(),
+            Setting::VoteSkipPercent => settings.vote_skip_percent.is_none(),
+            Setting::FairQueue => settings.fair_queue.is_none(),
+            Setting::Autoplay => settings.autoplay.is_none(),
+            Setting::IdleTimeoutMinutes => settings.idle_timeout_minutes.is_none(),
+            Setting::MaxTrackMinutes => settings.max_track_minutes.is_none(),
+            Setting::MaxUserTracks => settings.max_user_tracks.is_none(),
+            Setting::MaxQueueLength => settings.max_queue_length.is_none(),
+            Setting::AllowDuplicates => settings.allow_duplicates.is_none(),
+            Setting::LimitsExemptRole => settings.limits_exempt_role.is_none(),
+        }
+    }
+
+    pub fn value(&self, settings: &GuildSettings) -> String {
+        match self {
+            Setting::LeaveOnEmpty => settings.leave_on_empty().to_string(),
+            Setting::EmptyGraceSeconds => match settings.empty_grace_period() {
+                Some(grace) => format!("{} seconds", grace.as_secs()),
+                None => "none".to_string(),
+            },
+            Setting::KeepQueueOnLeave => settings.keep_queue_on_leave().to_string(),
+            Setting::Volume => format!("{}%", settings.volume()),
+            Setting::AnnounceTracks => settings.announce_tracks().to_string(),
+            Setting::DjRole => role_value(settings.dj_role),
+            Setting::VoteSkip => settings.vote_skip().to_string(),
+            Setting::VoteSkipPercent => format!("{}%", settings.vote_skip_percent()),
+            Setting::FairQueue => settings.fair_queue().to_string(),
+            Setting::Autoplay => settings.autoplay().to_string(),
+            Setting::IdleTimeoutMinutes => match settings.idle_timeout() {
+                Some(timeout) => format!("{} minutes", timeout.as_secs() / 60),
+                None => "none".to_string(),
+            },
+            Setting::MaxTrackMinutes => match settings.max_track_length() {
+                Some(max) => format!("{} minutes", max.as_secs() / 60),
+                None => "none".to_string(),
+            },
+            Setting::MaxUserTracks => limit_value(settings.max_user_tracks()),
+            Setting::MaxQueueLength => limit_value(settings.max_queue_length()),
+            Setting::AllowDuplicates => settings.allow_duplicates().to_string(),
+            Setting::LimitsExemptRole => role_value(settings.limits_exempt_role),
+        }
+    }
+
+    /// Parses `value` and stores it, returns a message for the user if it's invalid
+    pub fn set(&self, settings: &mut GuildSettings, value: &str) -> Result<(), String> {
+        match self {
+            Setting::LeaveOnEmpty => settings.leave_on_empty = Some(parse_bool(value)?),
+            Setting::EmptyGraceSeconds => {
+                settings.empty_grace_seconds = Some(parse_limit(value)? as u32)
+            }
+            Setting::KeepQueueOnLeave => settings.keep_queue_on_leave = Some(parse_bool(value)?),
+            Setting::Volume => settings.volume = Some(parse_volume(value)?),
+            Setting::AnnounceTracks => settings.announce_tracks = Some(parse_bool(value)?),
+            Setting::DjRole => settings.dj_role = parse_role(value)?,
+            Setting::VoteSkip => settings.vote_skip = Some(parse_bool(value)?),
+            Setting::VoteSkipPercent => settings.vote_skip_percent = Some(parse_percent(value)?),
+            Setting::FairQueue => settings.fair_queue = Some(parse_bool(value)?),
+            Setting::Autoplay => settings.autoplay = Some(parse_bool(value)?),
+            Setting::IdleTimeoutMinutes => {
+                settings.idle_timeout_minutes = Some(parse_limit(value)? as u32)
+            }
+            Setting::MaxTrackMinutes => {
+                settings.max_track_minutes = Some(parse_limit(value)? as u32)
+            }
+            Setting::MaxUserTracks => settings.max_user_tracks = Some(parse_limit(value)?),
+            Setting::MaxQueueLength => settings.max_queue_length = Some(parse_limit(value)?),
+            Setting::AllowDuplicates => settings.allow_duplicates = Some(parse_bool(value)?),
+            Setting::LimitsExemptRole => settings.limits_exempt_role = parse_role(value)?,
+        }
+        Ok(())
+    }
+
+    pub fn reset(&self, settings: &mut GuildSettings) {
+        match self {
+            Setting::LeaveOnEmpty => settings.leave_on_empty = None,
+            Setting::EmptyGraceSeconds => settings.empty_grace_seconds = None,
+            Setting::KeepQueueOnLeave => settings.keep_queue_on_leave = None,
+            Setting::Volume => settings.volume = None,
+            Setting::AnnounceTracks => settings.announce_tracks = None,
+            Setting::DjRole => settings.dj_role = None,
+            Setting::VoteSkip => settings.vote_skip = None,
+            Setting::VoteSkipPercent => settings.vote_skip_percent = None,
+            Setting::FairQueue => settings.fair_queue = None,
+            Setting::Autoplay => settings.autoplay = None,
+            Setting::IdleTimeoutMinutes => settings.idle_timeout_minutes = None,
+            Setting::MaxTrackMinutes => settings.max_track_minutes = None,
+            Setting::MaxUserTracks => settings.max_user_tracks = None,
+            Setting::MaxQueueLength => settings.max_queue_length = None,
+            Setting::AllowDuplicates => settings.allow_duplicates = None,
+            Setting::LimitsExemptRole => settings.limits_exempt_role = None,
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!(
+            "`{value}` isn't a valid value, use `true` or `false`"
+        )),
+    }
+}
+
+fn parse_volume(value: &str) -> Result<u16, String> {
+    match value.trim_end_matches('%').parse::<u16>() {
+        Ok(volume) if volume <= MAX_VOLUME => Ok(volume),
+        _ => Err(format!(
+            "`{value}` isn't a valid volume, use a number from 0 to {MAX_VOLUME}"
+        )),
+    }
+}
+
+fn parse_percent(value: &str) -> Result<u8, String> {
+    match value.trim_end_matches('%').parse::<u8>() {
+        Ok(percent) if (1..=100).contains(&percent) => Ok(percent),
+        _ => Err(format!(
+            "`{value}` isn't a valid percentage, use a number from 1 to 100"
+        )),
+    }
+}
+
+fn limit_value(limit: Option<usize>) -> String {
+    match limit {
+        Some(max) => max.to_string(),
+        None => "none".to_string(),
+    }
+}
+
+fn role_value(role: Option<u64>) -> String {
+    match role {
+        Some(role_id) => format!("<@&{role_id}>"),
+        None => "none".to_string(),
+    }
+}
+
+/// Takes a number, or `none` for no limit which is stored as 0
+fn parse_limit(value: &str) -> Result<usize, String> {
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(0);
+    }
+    value
+        .parse::<u32>()
+        .map(|limit| limit as usize)
+        .map_err(|_| format!("`{value}` isn't a valid limit, use a number or `none`"))
+}
+
+/// Takes a role mention or id, or `none` to remove the role
+fn parse_role(value: &str) -> Result<Option<u64>, String> {
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    value
+        .trim_start_matches("<@&")
+        .trim_end_matches('>')
+        .parse::<u64>()
+        .map(Some)
+        .map_err(|_| format!("`{value}` isn't a role, mention one or use `none`"))
+}
+
+fn settings_dir() -> PathBuf {
+    storage::data_path("settings")
+}
+
+fn settings_path(guild_id: serenity::GuildId) -> PathBuf {
+    settings_dir().join(format!("{guild_id}.json"))
+}
+
+#[derive(Default)]
+pub struct SettingsStore {
+    guilds: RwLock<HashMap<serenity::GuildId, GuildSettings>>,
+}
+
+impl SettingsStore {
+    pub async fn load() -> eyre::Result<Self> {
+        let mut guilds = HashMap::new();
+        let mut entries = match tokio::fs::read_dir(settings_dir()).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some(guild_id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            if let Some(settings) = storage::load::<GuildSettings>(&path).await? {
+                guilds.insert(serenity::GuildId::new(guild_id), settings);
+            }
+        }
+        Ok(Self {
+            guilds: RwLock::new(guilds),
+        })
+    }
+
+    pub async fn get(&self, guild_id: serenity::GuildId) -> GuildSettings {
+        self.guilds
+            .read()
+            .await
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Modifies the guild's settings and writes them to disk
+    pub async fn modify<F, O>(&self, guild_id: serenity::GuildId, func: F) -> eyre::Result<O>
+    where
+        F: FnOnce(&mut GuildSettings) -> O,
+    {
+        let mut guilds = self.guilds.write().await;
+        let settings = guilds.entry(guild_id).or_default();
+        let output = func(settings);
+        storage::save(&settings_path(guild_id), settings).await?;
+        Ok(output)
+    }
+
+    /// Like [`Self::modify`], but nothing changes or gets written if `func` fails
+    pub async fn try_modify<F, O, E>(
+        &self,
+        guild_id: serenity::GuildId,
+        func: F,
+    ) -> eyre::Result<Result<O, E>>
+    where
+        F: FnOnce(&mut GuildSettings) -> Result<O, E>,
+    {
+        let mut guilds = self.guilds.write().await;
+        let mut settings = guilds.get(&guild_id).cloned().unwrap_or_default();
+        let output = match func(&mut settings) {
+            Ok(output) => output,
+            Err(e) => return Ok(Err(e)),
+        };
+        storage::save(&settings_path(guild_id), &settings).await?;
+        guilds.insert(guild_id, settings);
+        Ok(Ok(output))
+    }
+}
+
+pub struct SettingsKey;
+
+impl TypeMapKey for SettingsKey {
+    type Value = Arc<SettingsStore>;
+}
+
+pub async fn get_settings_store(ctx: &serenity::Context) -> Arc<SettingsStore> {
+    let data = ctx.data.read().await;
+    data.get::<SettingsKey>().cloned().unwrap()
+}
+
+pub async fn get_guild_settings(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+) -> GuildSettings {
+    get_settings_store(ctx).await.get(guild_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_limit;
+
+    #[test]
+    fn parses_limits() {
+        assert_eq!(parse_limit("5"), Ok(5));
+        assert_eq!(parse_limit("0"), Ok(0));
+    }
+
+    #[test]
+    fn none_is_no_limit() {
+        assert_eq!(parse_limit("none"), Ok(0));
+        assert_eq!(parse_limit("None"), Ok(0));
+    }
+
+    #[test]
+    fn rejects_invalid_limits() {
+        assert!(parse_limit("-1").is_err());
+        assert!(parse_limit("lots").is_err());
+        assert!(parse_limit("").is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::config::get_config;
+
+pub fn data_path(name: impl AsRef<Path>) -> PathBuf {
+    get_config().data_dir.join(name)
+}
+
+/// Reads a json file, returns `None` if it doesn't exist yet
+pub async fn load<T: DeserializeOwned>(path: &Path) -> eyre::Result<Option<T>> {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes a json file, going through a temporary file so a crash never leaves a half written one
+pub async fn save<T: Serialize>(path: &Path, value: &T) -> eyre::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    tokio::fs::write(&tmp_path, serde_json::to_vec_pretty(value)?).await?;
+    tokio::fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
+
+pub async fn remove(path: &Path) -> eyre::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::prelude::TypeMapKey;
+use songbird::tracks::TrackHandle;
+
+use crate::utils::{CustomMetadata, SourceKind};
+use crate::ytdl;
+use crate::ytdl::Entry;
+
+const SEARCH_RESULTS: usize = 5;
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+const CACHE_SIZE: usize = 256;
+const RECENT_TRACKS: usize = 25;
+// discord sends a request for every key press, only the last one is worth searching for
+const DEBOUNCE: Duration = Duration::from_millis(400);
+// discord gives up on autocomplete responses after 3 seconds
+const SEARCH_DEADLINE: Duration = Duration::from_millis(2000);
+
+/// A track that was queued in a guild, suggested again when it matches what's being typed
+#[derive(Clone, Debug)]
+pub struct RecentTrack {
+    pub title: String,
+    pub url: String,
+}
+
+struct CachedSearch {
+    fetched: Instant,
+    results: Vec<Entry>,
+}
+
+/// Everything `/play`'s autocomplete remembers between key presses
+#[derive(Default)]
+pub struct Suggestions {
+    searches: Mutex<HashMap<String, CachedSearch>>,
+    generation: AtomicU64,
+    latest: Mutex<HashMap<serenity::UserId, u64>>,
+    recent: Mutex<HashMap<serenity::GuildId, VecDeque<RecentTrack>>>,
+}
+
+impl Suggestions {
+    /// Remembers a queued track for the guild, tracks without a url can't be suggested
+    ///
+    /// Library tracks are left out, their url is a path on this machine that `/play` can't take,
+    /// and so are the ones autoplay picked, nobody here asked for those
+    pub fn record(&self, guild_id: serenity::GuildId, metadata: &CustomMetadata) {
+        if !matches!(metadata.kind, SourceKind::YoutubeDl | SourceKind::Http) || metadata.autoplay {
+            return;
+        }
+        let Some(url) = metadata.aux_metadata.source_url.clone() else {
+            return;
+        };
+        let title = metadata
+            .aux_metadata
+            .title
+            .clone()
+            .unwrap_or("Untitled".to_string());
+
+        let mut recent = self.recent.lock().unwrap();
+        let tracks = recent.entry(guild_id).or_default();
+        tracks.retain(|track| track.url != url);
+        tracks.push_front(RecentTrack { title, url });
+        tracks.truncate(RECENT_TRACKS);
+    }
+
+    /// Remembers tracks someone just queued, restored or looped ones don't count as asked for again
+    pub fn record_queued(&self, guild_id: serenity::GuildId, handles: &[TrackHandle]) {
+        for trackhandle in handles {
+            self.record(guild_id, &trackhandle.data::<CustomMetadata>());
+        }
+    }
+
+    /// The guild's recently queued tracks whose title contains `partial`, newest first
+    pub fn recent(&self, guild_id: serenity::GuildId, partial: &str) -> Vec<RecentTrack> {
+        let partial = partial.to_lowercase();
+        self.recent
+            .lock()
+            .unwrap()
+            .get(&guild_id)
+            .map(|tracks| {
+                tracks
+                    .iter()
+                    .filter(|track| track.title.to_lowercase().contains(&partial))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Waits a bit, returns `false` if the user typed something else in the meantime
+    pub async fn debounce(&self, user_id: serenity::UserId) -> bool {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed);
+        self.latest.lock().unwrap().insert(user_id, generation);
+
+        tokio::time::sleep(DEBOUNCE).await;
+
+        self.latest.lock().unwrap().get(&user_id) == Some(&generation)
+    }
+
+    fn cached(&self, query: &str) -> Option<Vec<Entry>> {
+        self.searches
+            .lock()
+            .unwrap()
+            .get(query)
+            .filter(|cached| cached.fetched.elapsed() < CACHE_TTL)
+            .map(|cached| cached.results.clone())
+    }
+
+    fn store(&self, query: String, results: Vec<Entry>) {
+        let mut searches = self.searches.lock().unwrap();
+        searches.retain(|_, cached| cached.fetched.elapsed() < CACHE_TTL);
+        if searches.len() >= CACHE_SIZE {
+            if let Some(oldest) = searches
+                .iter()
+                .min_by_key(|(_, cached)| cached.fetched)
+                .map(|(query, _)| query.clone())
+            {
+                searches.remove(&oldest);
+            }
+        }
+        searches.insert(
+            query,
+            CachedSearch {
+                fetched: Instant::now(),
+                results,
+            },
+        );
+    }
+
+    /// Search results for `query`, from the cache if possible
+    ///
+    /// Returns `None` if yt-dlp doesn't answer in time, the results still end up in the cache
+    /// for the next key press
+    pub async fn search(self: &Arc<Self>, query: &str) -> Option<Vec<Entry>> {
+        let query = query.to_lowercase();
+        if let Some(results) = self.cached(&query) {
+            return Some(results);
+        }
+
+        let suggestions = self.clone();
+        let search = tokio::spawn(async move {
+            match ytdl::search(&query, SEARCH_RESULTS).await {
+                Ok(results) => {
+                    suggestions.store(query, results.clone());
+                    Some(results)
+                }
+                Err(e) => {
+                    log::warn!("Autocomplete search failed: {:?}", e);
+                    None
+                }
+            }
+        });
+
+        tokio::time::timeout(SEARCH_DEADLINE, search)
+            .await
+            .ok()?
+            .ok()?
+    }
+}
+
+pub struct SuggestionsKey;
+
+impl TypeMapKey for SuggestionsKey {
+    type Value = Arc<Suggestions>;
+}
+
+pub async fn get_suggestions(ctx: &serenity::Context) -> Arc<Suggestions> {
+    let data = ctx.data.read().await;
+    data.get::<SuggestionsKey>().cloned().unwrap()
+}
@@ -1,5 +1,7 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
+use poise::serenity_prelude as serenity;
 use poise::serenity_prelude::prelude::TypeMapKey;
 use songbird::Call;
 use tokio::sync::Mutex;
@@ -13,6 +15,16 @@ pub fn human_print_time(dur: std::time::Duration) -> String {
     format!("[{:0>2}:{:0>2}]", minutes, secs)
 }
 
+/// Cuts `text` down to `length` characters, ending it with "..." if it was longer
+pub fn truncate(text: &str, length: usize) -> String {
+    if text.chars().count() <= length {
+        return text.to_string();
+    }
+    let mut text = text.chars().take(length - 3).collect::<String>();
+    text.push_str("...");
+    text
+}
+
 pub async fn get_handler_lock(&ctx: &Context<'_>) -> Result<Option<Arc<Mutex<Call>>>, Error> {
     let Some(guild) = ctx.guild_id() else {
         ctx.say("I can only operate in a server!").await?;
@@ -27,6 +39,108 @@ pub async fn get_handler_lock(&ctx: &Context<'_>) -> Result<Option<Arc<Mutex<Cal
     Ok(Some(handler_lock))
 }
 
+/// Whether the author is one of the owners in config.toml, which can change while running
+pub fn is_owner(ctx: &Context<'_>) -> bool {
+    crate::config::get_config()
+        .owners
+        .contains(&ctx.author().id.get())
+}
+
+/// Whether the author is a bot owner or has the Manage Server permission
+pub async fn can_manage_guild(ctx: &Context<'_>) -> bool {
+    if is_owner(ctx) {
+        return true;
+    }
+
+    ctx.author_member()
+        .await
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_guild())
+}
+
+/// The voice channel the author is in, telling them if they aren't in one
+pub async fn author_channel(
+    ctx: &Context<'_>,
+) -> Result<Option<(serenity::GuildId, serenity::ChannelId)>, Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("I can only operate in a server!").await?;
+        return Ok(None);
+    };
+
+    let Some(channel_id) = guild_id
+        .to_guild_cached(ctx.serenity_context())
+        .unwrap()
+        .voice_states
+        .get(&ctx.author().id)
+        .and_then(|vstate| vstate.channel_id)
+    else {
+        ctx.say("You've gotta be in a voice channel to play!")
+            .await?;
+        return Ok(None);
+    };
+
+    Ok(Some((guild_id, channel_id)))
+}
+
+/// Joins the voice channel the author is in, telling them if they aren't in one
+pub async fn join_author_channel(ctx: &Context<'_>) -> Result<Option<serenity::GuildId>, Error> {
+    let Some((guild_id, channel_id)) = author_channel(ctx).await? else {
+        return Ok(None);
+    };
+    ctx.defer().await?;
+
+    // the queue's announcements go wherever music was last asked for
+    crate::announcer::get_announcer(ctx.serenity_context())
+        .await
+        .set_channel(guild_id, ctx.channel_id());
+
+    let manager = songbird::get(ctx.serenity_context()).await.unwrap().clone();
+    // a new session replaces whatever queue was kept from the last one
+    if manager.get(guild_id).is_none() {
+        if let Err(e) = crate::persistence::drop_kept_queue(guild_id).await {
+            log::error!("Can't drop the kept queue: {:?}", e);
+        }
+    }
+    manager.join(guild_id, channel_id).await?;
+    Ok(Some(guild_id))
+}
+
+/// The people (not bots) in the voice channel the bot is in, `None` if it isn't in one
+pub async fn listeners(
+    ctx: &serenity::Context,
+    guild_id: serenity::GuildId,
+) -> Result<Option<HashSet<serenity::UserId>>, Error> {
+    let manager = songbird::get(ctx).await.unwrap().clone();
+
+    let Some(call) = manager.get(guild_id) else {
+        return Ok(None);
+    };
+
+    let Some(cur_channel) = call
+        .lock()
+        .await
+        .current_channel()
+        .map(|item| item.0.into())
+    else {
+        return Ok(None);
+    };
+
+    let channels = guild_id.channels(&ctx.http).await?;
+
+    let Some(channel) = channels.get(&cur_channel).cloned() else {
+        return Ok(None);
+    };
+
+    Ok(Some(
+        channel
+            .members(&ctx.cache)?
+            .into_iter()
+            .filter(|member| !member.user.bot)
+            .map(|member| member.user.id)
+            .collect(),
+    ))
+}
+
 // YtDl requests need an HTTP client to operate -- we'll create and store our own.
 pub struct HttpKey;
 
@@ -34,7 +148,137 @@ impl TypeMapKey for HttpKey {
     type Value = reqwest::Client;
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SourceKind {
+    /// Anything yt-dlp can resolve
+    #[default]
+    YoutubeDl,
+    /// A direct audio stream, like an icecast/shoutcast radio
+    Http,
+    /// A file from the music library, the source url is its path
+    File,
+}
+
+#[derive(Default)]
 pub struct CustomMetadata {
     pub aux_metadata: songbird::input::AuxMetadata,
     pub requested_by: String,
+    pub kind: SourceKind,
+    /// Livestreams and radios have no end, so they can't be seeked or shown with a progress bar
+    pub live: bool,
+    /// The latest title a radio sent along with its stream
+    pub stream_title: std::sync::Mutex<Option<String>>,
+    /// Effects the track is played with, they change how songbird's positions map onto the audio
+    pub filters: Vec<crate::effects::Filter>,
+    /// Picked by autoplay rather than by someone, so it gives way to anything people queue
+    pub autoplay: bool,
+    /// Set when the track was taken out of the queue on purpose, like when it's removed or swapped
+    /// for a copy with new filters, so it isn't recorded as played or looped back in
+    pub discarded: std::sync::atomic::AtomicBool,
+}
+
+/// Serializable copy of [`songbird::input::AuxMetadata`]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+pub struct SavedMetadata {
+    pub track: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub date: Option<String>,
+    pub channels: Option<u8>,
+    pub channel: Option<String>,
+    pub start_time: Option<std::time::Duration>,
+    pub duration: Option<std::time::Duration>,
+    pub sample_rate: Option<u32>,
+    pub source_url: Option<String>,
+    pub title: Option<String>,
+    pub thumbnail: Option<String>,
+}
+
+impl From<&songbird::input::AuxMetadata> for SavedMetadata {
+    fn from(value: &songbird::input::AuxMetadata) -> Self {
+        Self {
+            track: value.track.clone(),
+            artist: value.artist.clone(),
+            album: value.album.clone(),
+            date: value.date.clone(),
+            channels: value.channels,
+            channel: value.channel.clone(),
+            start_time: value.start_time,
+            duration: value.duration,
+            sample_rate: value.sample_rate,
+            source_url: value.source_url.clone(),
+            title: value.title.clone(),
+            thumbnail: value.thumbnail.clone(),
+        }
+    }
+}
+
+impl From<SavedMetadata> for songbird::input::AuxMetadata {
+    fn from(value: SavedMetadata) -> Self {
+        Self {
+            track: value.track,
+            artist: value.artist,
+            album: value.album,
+            date: value.date,
+            channels: value.channels,
+            channel: value.channel,
+            start_time: value.start_time,
+            duration: value.duration,
+            sample_rate: value.sample_rate,
+            source_url: value.source_url,
+            title: value.title,
+            thumbnail: value.thumbnail,
+        }
+    }
+}
+
+/// A track as it's stored on disk, enough to recreate it later
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct SavedTrack {
+    pub source: String,
+    pub metadata: SavedMetadata,
+    pub requested_by: String,
+    #[serde(default)]
+    pub kind: SourceKind,
+    #[serde(default)]
+    pub live: bool,
+}
+
+impl SavedTrack {
+    /// Returns `None` if the track doesn't have a source url to recreate it from
+    pub fn from_handle(handle: &songbird::tracks::TrackHandle) -> Option<Self> {
+        let data = handle.data::<CustomMetadata>();
+        Some(Self {
+            source: data.aux_metadata.source_url.clone()?,
+            metadata: (&data.aux_metadata).into(),
+            requested_by: data.requested_by.clone(),
+            kind: data.kind,
+            live: data.live,
+        })
+    }
+
+    pub fn into_source(
+        self,
+        http_client: reqwest::Client,
+    ) -> (songbird::input::Input, CustomMetadata) {
+        let source = match self.kind {
+            SourceKind::YoutubeDl => {
+                songbird::input::YoutubeDl::new(http_client, self.source).into()
+            }
+            SourceKind::Http => songbird::input::HttpRequest::new(http_client, self.source).into(),
+            SourceKind::File => {
+                songbird::input::File::new(std::path::PathBuf::from(self.source)).into()
+            }
+        };
+        (
+            source,
+            CustomMetadata {
+                aux_metadata: self.metadata.into(),
+                requested_by: self.requested_by,
+                kind: self.kind,
+                live: self.live,
+                ..Default::default()
+            },
+        )
+    }
 }
@@ -0,0 +1,83 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::prelude::TypeMapKey;
+use songbird::tracks::TrackHandle;
+
+struct TrackVotes {
+    trackhandle: TrackHandle,
+    voters: HashSet<serenity::UserId>,
+}
+
+/// Votes to skip each guild's current track
+#[derive(Default)]
+pub struct SkipVotes {
+    guilds: Mutex<HashMap<serenity::GuildId, TrackVotes>>,
+}
+
+impl SkipVotes {
+    /// Counts the user's vote to skip `trackhandle`, returns how many of `listeners` voted for it
+    ///
+    /// Votes for whatever played before are thrown away, so every track starts from zero
+    pub fn vote(
+        &self,
+        guild_id: serenity::GuildId,
+        trackhandle: &TrackHandle,
+        user_id: serenity::UserId,
+        listeners: &HashSet<serenity::UserId>,
+    ) -> usize {
+        let mut guilds = self.guilds.lock().unwrap();
+        let votes = guilds.entry(guild_id).or_insert_with(|| TrackVotes {
+            trackhandle: trackhandle.clone(),
+            voters: HashSet::new(),
+        });
+        if votes.trackhandle.uuid() != trackhandle.uuid() {
+            votes.trackhandle = trackhandle.clone();
+            votes.voters.clear();
+        }
+        votes.voters.insert(user_id);
+
+        // people who left the channel don't count anymore
+        votes.voters.intersection(listeners).count()
+    }
+}
+
+/// How many votes it takes to skip with `listeners` people listening
+pub fn votes_needed(listeners: usize, percent: u8) -> usize {
+    (listeners * percent as usize).div_ceil(100).max(1)
+}
+
+pub struct SkipVotesKey;
+
+impl TypeMapKey for SkipVotesKey {
+    type Value = Arc<SkipVotes>;
+}
+
+pub async fn get_skip_votes(ctx: &serenity::Context) -> Arc<SkipVotes> {
+    let data = ctx.data.read().await;
+    data.get::<SkipVotesKey>().cloned().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::votes_needed;
+
+    #[test]
+    fn rounds_up() {
+        assert_eq!(votes_needed(3, 50), 2);
+        assert_eq!(votes_needed(4, 50), 2);
+        assert_eq!(votes_needed(10, 33), 4);
+    }
+
+    #[test]
+    fn needs_everyone_at_100_percent() {
+        assert_eq!(votes_needed(7, 100), 7);
+    }
+
+    #[test]
+    fn needs_at_least_one_vote() {
+        assert_eq!(votes_needed(0, 50), 1);
+        assert_eq!(votes_needed(1, 1), 1);
+    }
+}
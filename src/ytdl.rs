@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use songbird::input::AuxMetadata;
+
+const YOUTUBE_DL_COMMAND: &str = "yt-dlp";
+
+pub struct Playlist {
+    pub title: Option<String>,
+    pub entries: Vec<Entry>,
+}
+
+/// Something yt-dlp can play, with what it knows about it
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub aux_metadata: AuxMetadata,
+    /// A missing duration doesn't mean much, flat playlist entries often leave it out
+    pub live: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct FlatPlaylist {
+    title: Option<String>,
+    #[serde(default)]
+    entries: Vec<FlatEntry>,
+}
+
+/// An entry of a flat playlist, or the info `-j` prints for a single video
+#[derive(serde::Deserialize)]
+struct FlatEntry {
+    /// The page of the video in a flat playlist, but the stream itself with `-j`
+    url: Option<String>,
+    webpage_url: Option<String>,
+    title: Option<String>,
+    track: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    release_date: Option<String>,
+    upload_date: Option<String>,
+    duration: Option<f64>,
+    channel: Option<String>,
+    uploader: Option<String>,
+    thumbnail: Option<String>,
+    #[serde(default)]
+    thumbnails: Vec<FlatThumbnail>,
+    live_status: Option<String>,
+    is_live: Option<bool>,
+}
+
+#[derive(serde::Deserialize)]
+struct FlatThumbnail {
+    url: String,
+}
+
+impl From<FlatEntry> for Entry {
+    fn from(value: FlatEntry) -> Self {
+        let live = value.is_live == Some(true) || value.live_status.as_deref() == Some("is_live");
+        Entry {
+            aux_metadata: AuxMetadata {
+                source_url: value.webpage_url.or(value.url),
+                title: value.title,
+                track: value.track,
+                artist: value.artist,
+                album: value.album,
+                date: value.release_date.or(value.upload_date),
+                duration: value
+                    .duration
+                    .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                    .map(Duration::from_secs_f64),
+                channel: value.channel.or(value.uploader),
+                thumbnail: value.thumbnail.or(value
+                    .thumbnails
+                    .into_iter()
+                    .last()
+                    .map(|item| item.url)),
+                ..Default::default()
+            },
+            live,
+        }
+    }
+}
+
+/// Whether `query` links a whole playlist, a video opened from a playlist or mix is still one video
+pub fn is_playlist_url(query: &str) -> bool {
+    regex::Regex::new(r"^https?://\S*(/playlist\?\S*\blist=|/sets/|/album/)")
+        .unwrap()
+        .is_match(query)
+}
+
+/// Searches youtube, returning up to `count` results without resolving each of them
+pub async fn search(query: &str, count: usize) -> eyre::Result<Vec<Entry>> {
+    let search = format!("ytsearch{count}:{query}");
+    Ok(flat_playlist(&search, 0, Some(count)).await?.entries)
+}
+
+/// Tracks youtube thinks go with a video, taken from the mix it makes for it
+///
+/// Returns `None` if `url` isn't a youtube video
+pub async fn related(url: &str, count: usize) -> eyre::Result<Option<Vec<Entry>>> {
+    let Some(id) = regex::Regex::new(r"(?:[?&]v=|youtu\.be/|/shorts/)([\w-]{11})")
+        .unwrap()
+        .captures(url)
+        .map(|captures| captures[1].to_string())
+    else {
+        return Ok(None);
+    };
+    let mix = format!("https://www.youtube.com/watch?v={id}&list=RD{id}");
+    // the mix starts with the video itself
+    Ok(Some(flat_playlist(&mix, 1, Some(count)).await?.entries))
+}
+
+/// Looks up a single video, or the first result if `query` isn't a url
+pub async fn info(query: &str) -> eyre::Result<Entry> {
+    let output = tokio::process::Command::new(YOUTUBE_DL_COMMAND)
+        .args(["-j", "--no-playlist", query])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        eyre::bail!(
+            "{YOUTUBE_DL_COMMAND} failed with non-zero status code: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let Some(line) = output
+        .stdout
+        .split(|&byte| byte == b'\n')
+        .find(|line| !line.is_empty())
+    else {
+        eyre::bail!("No results found for `{query}`");
+    };
+    Ok(serde_json::from_slice::<FlatEntry>(line)?.into())
+}
+
+/// Lists a playlist's entries without resolving each of them, which is what makes big playlists slow
+pub async fn flat_playlist(
+    url: &str,
+    offset: usize,
+    limit: Option<usize>,
+) -> eyre::Result<Playlist> {
+    let items = format!(
+        "{}:{}",
+        offset + 1,
+        limit
+            .map(|limit| (offset + limit).to_string())
+            .unwrap_or_default()
+    );
+    let output = tokio::process::Command::new(YOUTUBE_DL_COMMAND)
+        .args(["-J", "--flat-playlist", "--playlist-items", &items, url])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        eyre::bail!(
+            "{YOUTUBE_DL_COMMAND} failed with non-zero status code: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let playlist = serde_json::from_slice::<FlatPlaylist>(&output.stdout)?;
+
+    Ok(Playlist {
+        title: playlist.title,
+        entries: playlist
+            .entries
+            .into_iter()
+            .filter(|entry| entry.url.is_some())
+            .map(Into::into)
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_playlist_url, Entry, FlatEntry};
+
+    fn entry(json: &str) -> Entry {
+        serde_json::from_str::<FlatEntry>(json).unwrap().into()
+    }
+
+    #[test]
+    fn entries_without_a_duration_are_not_live() {
+        let entry = entry(r#"{"url": "https://soundcloud.com/someone/track"}"#);
+        assert!(!entry.live);
+        assert_eq!(entry.aux_metadata.duration, None);
+    }
+
+    #[test]
+    fn reads_the_live_status() {
+        assert!(entry(r#"{"live_status": "is_live"}"#).live);
+        assert!(entry(r#"{"is_live": true}"#).live);
+        assert!(!entry(r#"{"live_status": "was_live", "is_live": false}"#).live);
+    }
+
+    #[test]
+    fn prefers_the_webpage_over_the_stream() {
+        let entry = entry(
+            r#"{"url": "https://stream.example/audio", "webpage_url": "https://www.youtube.com/watch?v=uaH2lsqnYhE"}"#,
+        );
+        assert_eq!(
+            entry.aux_metadata.source_url.as_deref(),
+            Some("https://www.youtube.com/watch?v=uaH2lsqnYhE")
+        );
+    }
+
+    #[test]
+    fn playlist_urls_are_playlists() {
+        assert!(is_playlist_url(
+            "https://www.youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"
+        ));
+        assert!(is_playlist_url(
+            "https://music.youtube.com/playlist?feature=share&list=OLAK5uy_k"
+        ));
+        assert!(is_playlist_url(
+            "https://soundcloud.com/someone/sets/mixtape"
+        ));
+        assert!(is_playlist_url("https://someone.bandcamp.com/album/record"));
+    }
+
+    #[test]
+    fn videos_from_a_playlist_are_single_videos() {
+        assert!(!is_playlist_url(
+            "https://www.youtube.com/watch?v=uaH2lsqnYhE&list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"
+        ));
+        assert!(!is_playlist_url(
+            "https://www.youtube.com/watch?v=uaH2lsqnYhE&list=RDuaH2lsqnYhE&start_radio=1"
+        ));
+        assert!(!is_playlist_url(
+            "https://youtu.be/uaH2lsqnYhE?list=RDuaH2lsqnYhE"
+        ));
+        assert!(!is_playlist_url(
+            "https://www.youtube.com/watch?v=uaH2lsqnYhE"
+        ));
+        assert!(!is_playlist_url("lofi playlist?list=1"));
+    }
+}